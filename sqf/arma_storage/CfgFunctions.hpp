@@ -0,0 +1,61 @@
+// Generated by `arma-storage-cli sqf`, do not edit
+// Copy the library into the mission as arma_storage and add to description.ext:
+// #include "arma_storage\CfgFunctions.hpp"
+class CfgFunctions {
+    class AS {
+        tag = "AS";
+        class arma_storage {
+            file = "arma_storage\functions";
+            class call {};
+            class initCallbacks { postInit = 1; };
+            class addCallback {};
+            class removeCallback {};
+            class help {};
+            class errorCodes {};
+            class errorName {};
+            class info {};
+            class dump {};
+            class open {};
+            class close {};
+            class read {};
+            class write {};
+            class get {};
+            class set {};
+            class erase {};
+            class exists {};
+            class getFiles {};
+            class increment {};
+            class decrement {};
+            class push {};
+            class pushUnique {};
+            class removeAt {};
+            class removeValue {};
+            class toggle {};
+            class compareAndSet {};
+            class getMany {};
+            class setMany {};
+            class begin {};
+            class txSet {};
+            class txErase {};
+            class commit {};
+            class rollback {};
+            class keys {};
+            class keysWithPrefix {};
+            class keysMatching {};
+            class ttl {};
+            class diskFiles {};
+            class openStorages {};
+            class diskStorages {};
+            class deleteFile {};
+            class export {};
+            class import {};
+            class stats {};
+            class compression {};
+            class verify {};
+            class salvage {};
+            class history {};
+            class exportAudit {};
+            class as {};
+        };
+    };
+};
@@ -0,0 +1,174 @@
+//! Operations changing a single value in place, so scripts do not race each
+//! other with get, modify and set
+use crate::{
+    journal::Record,
+    storage::{StorageError, StoragePool},
+    Value,
+};
+use anyhow::{ensure, Result};
+use log::info;
+
+impl StoragePool {
+    /// Add `amount` to a number. A missing key counts as `0`.
+    pub fn increment(&self, name: &str, key: &str, amount: f32) -> Result<Value> {
+        let value = self.update(name, key, Some(Value::Number(0.)), |value| {
+            match value {
+                Value::Number(number) => *number += amount,
+                _ => return Err(type_mismatch(key, "SCALAR", value)),
+            }
+
+            Ok(value.clone())
+        })?;
+
+        info!("Incremented storage {} key {} by {}", name, key, amount);
+
+        Ok(value)
+    }
+
+    /// Subtract `amount` from a number. A missing key counts as `0`.
+    pub fn decrement(&self, name: &str, key: &str, amount: f32) -> Result<Value> {
+        self.increment(name, key, -amount)
+    }
+
+    /// Append `element` to an array and return the new length. A missing key
+    /// counts as an empty array.
+    pub fn push(&self, name: &str, key: &str, element: &Value) -> Result<usize> {
+        let len = self.update(name, key, Some(Value::Array(Vec::new())), |value| {
+            let array = as_array(key, value)?;
+            array.push(element.clone());
+
+            Ok(array.len())
+        })?;
+
+        info!("Pushed to storage {} key {}", name, key);
+
+        Ok(len)
+    }
+
+    /// Append `element` to an array if it is not already contained.
+    /// Returns whether the element was added.
+    pub fn push_unique(&self, name: &str, key: &str, element: &Value) -> Result<bool> {
+        let added = self.update(name, key, Some(Value::Array(Vec::new())), |value| {
+            let array = as_array(key, value)?;
+
+            if array.contains(element) {
+                return Ok(false);
+            }
+
+            array.push(element.clone());
+
+            Ok(true)
+        })?;
+
+        if added {
+            info!("Pushed unique to storage {} key {}", name, key);
+        }
+
+        Ok(added)
+    }
+
+    /// Remove the element at `index` from an array and return it.
+    pub fn remove_at(&self, name: &str, key: &str, index: usize) -> Result<Value> {
+        let element = self.update(name, key, None, |value| {
+            let array = as_array(key, value)?;
+
+            ensure!(
+                index < array.len(),
+                StorageError::IndexOutOfBounds {
+                    key: key.to_owned(),
+                    index,
+                    len: array.len(),
+                }
+            );
+
+            Ok(array.remove(index))
+        })?;
+
+        info!("Removed index {} from storage {} key {}", index, name, key);
+
+        Ok(element)
+    }
+
+    /// Remove every occurrence of `element` from an array and return how many
+    /// were removed.
+    pub fn remove_value(&self, name: &str, key: &str, element: &Value) -> Result<usize> {
+        let removed = self.update(name, key, None, |value| {
+            let array = as_array(key, value)?;
+
+            let len = array.len();
+            array.retain(|value| value != element);
+
+            Ok(len - array.len())
+        })?;
+
+        info!(
+            "Removed {} values from storage {} key {}",
+            removed, name, key
+        );
+
+        Ok(removed)
+    }
+
+    /// Invert a boolean and return the new state.
+    pub fn toggle(&self, name: &str, key: &str) -> Result<bool> {
+        let state = self.update(name, key, None, |value| match value {
+            Value::Boolean(boolean) => {
+                *boolean = !*boolean;
+                Ok(*boolean)
+            }
+            _ => Err(type_mismatch(key, "BOOL", value)),
+        })?;
+
+        info!("Toggled storage {} key {}", name, key);
+
+        Ok(state)
+    }
+
+    /// Replace the value of a single key with `f` applied to a copy of it, or
+    /// to `default` if the key is missing. If `f` fails or the limits are
+    /// exceeded the value is left unchanged.
+    fn update<T>(
+        &self,
+        name: &str,
+        key: &str,
+        default: Option<Value>,
+        f: impl FnOnce(&mut Value) -> Result<T>,
+    ) -> Result<T> {
+        self.with_storage_mut(name, |storage| {
+            storage.purge(key);
+
+            let mut value = match (storage.data.get(key), default) {
+                (Some(value), _) => value.clone(),
+                (None, Some(default)) => default,
+                (None, None) => return Err(StorageError::StorageMissingKey(key.to_owned()).into()),
+            };
+
+            let result = f(&mut value)?;
+
+            self.check_limits(storage, &[(key, &value)])?;
+            self.record(storage, || {
+                let expires = storage.expiry.get(key).copied();
+                vec![Record::Set(key.to_owned(), value.clone(), expires)]
+            })?;
+            storage.insert(key.to_owned(), value);
+
+            Ok(result)
+        })
+    }
+}
+
+fn as_array<'a>(key: &str, value: &'a mut Value) -> Result<&'a mut Vec<Value>> {
+    match value {
+        Value::Array(array) => Ok(array),
+        _ => Err(type_mismatch(key, "ARRAY", value)),
+    }
+}
+
+fn type_mismatch(key: &str, expected: &'static str, found: &Value) -> anyhow::Error {
+    StorageError::TypeMismatch {
+        key: key.to_owned(),
+        expected,
+        found: found.type_name(),
+    }
+    .into()
+}
@@ -0,0 +1,421 @@
+//! Audit log of every change made to storages
+//!
+//! Each change is a line in `audit/changes.log` inside the storage directory.
+//! A line is an SQF array `[time, storage, key, old, new, caller]` where
+//! `time` is UTC like `"2026-10-19T12:34:56.789Z"`, `old` and `new` are `[]`
+//! if the key was missing or `[value]` and `caller` is the tag passed with
+//! [`StoragePool::with_caller`] or `""`. Lines a crash cut off are skipped
+//! when reading the log.
+//!
+//! [`StoragePool::history`] runs on the game thread, so it does not read the
+//! whole log every time. The offsets of the entries of every key are kept in
+//! an index, which only reads what was appended since it was last used. The
+//! log is never rotated by the extension. It can be moved away while the
+//! server is stopped, the index is rebuilt when the file got shorter.
+use crate::{
+    journal::Record,
+    storage::{format_time, now_millis, Storage, StoragePool},
+    Value,
+};
+use anyhow::Result;
+use log::{error, warn};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom, Write},
+    iter,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+const AUDIT_DIRECTORY: &str = "audit";
+const AUDIT_FILE: &str = "changes.log";
+
+thread_local! {
+    static CALLER: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// A single change of a key
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    /// UTC time like `2026-10-19T12:34:56.789Z`
+    pub time: String,
+    pub storage: String,
+    pub key: String,
+    /// `None` if the key was missing
+    pub old: Option<Value>,
+    /// `None` if the key was removed
+    pub new: Option<Value>,
+    pub caller: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn to_value(&self) -> Value {
+        let optional = |value: &Option<Value>| Value::Array(value.iter().cloned().collect());
+
+        Value::Array(vec![
+            Value::String(self.time.clone()),
+            Value::String(self.storage.clone()),
+            Value::String(self.key.clone()),
+            optional(&self.old),
+            optional(&self.new),
+            Value::String(self.caller.clone().unwrap_or_default()),
+        ])
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        let optional = |value: Value| match value {
+            Value::Array(mut value) if value.len() <= 1 => Some(value.pop()),
+            _ => None,
+        };
+        let string = |value: Value| match value {
+            Value::String(string) => Some(string),
+            _ => None,
+        };
+
+        let mut fields = match value {
+            Value::Array(fields) if fields.len() == 6 => fields.into_iter(),
+            _ => return None,
+        };
+
+        Some(Self {
+            time: string(fields.next()?)?,
+            storage: string(fields.next()?)?,
+            key: string(fields.next()?)?,
+            old: optional(fields.next()?)?,
+            new: optional(fields.next()?)?,
+            caller: Some(string(fields.next()?)?).filter(|caller| !caller.is_empty()),
+        })
+    }
+}
+
+/// Byte ranges of the entries of every key in the log
+#[derive(Debug, Default)]
+struct Index {
+    /// The log is read up to here, the end of the last entry
+    len: u64,
+    keys: HashMap<(String, String), Vec<Range<u64>>>,
+}
+
+/// Appends changes to the audit log file
+#[derive(Debug)]
+pub(crate) struct AuditLog {
+    path: PathBuf,
+    file: Mutex<Option<File>>,
+    index: Mutex<Index>,
+}
+
+impl AuditLog {
+    pub fn new(storage_path: &Path) -> Self {
+        Self {
+            path: storage_path.join(AUDIT_DIRECTORY).join(AUDIT_FILE),
+            file: Mutex::new(None),
+            index: Mutex::new(Index::default()),
+        }
+    }
+
+    /// Log the changes `records` are about to make to `storage`. Failing to
+    /// log does not stop the change.
+    pub fn log(&self, storage: &Storage, records: &[Record]) {
+        let time = format_time(now_millis());
+        let caller = CALLER.with(|caller| caller.borrow().clone());
+
+        let lines: String = changes(storage, records)
+            .into_iter()
+            .map(|(key, old, new)| {
+                let entry = AuditEntry {
+                    time: time.clone(),
+                    storage: storage.name().to_owned(),
+                    key,
+                    old,
+                    new,
+                    caller: caller.clone(),
+                };
+
+                format!("{}\n", entry.to_value().as_sqf())
+            })
+            .collect();
+
+        if let Err(err) = self.append(&lines) {
+            error!("Could not write audit log: {:#}", err);
+        }
+    }
+
+    fn append(&self, lines: &str) -> Result<()> {
+        let mut file = self.file.lock().unwrap();
+
+        let open = match &mut *file {
+            Some(open) => open,
+            None => {
+                fs::create_dir_all(self.path.parent().unwrap())?;
+                let mut open = OpenOptions::new()
+                    .create(true)
+                    .read(true)
+                    .append(true)
+                    .open(&self.path)?;
+
+                // an append cut off by a crash must not swallow the next line
+                if !ends_with_newline(&mut open)? {
+                    open.write_all(b"\n")?;
+                }
+
+                file.insert(open)
+            }
+        };
+
+        if let Err(err) = open.write_all(lines.as_bytes()) {
+            // check the end of the file again before the next append
+            *file = None;
+            return Err(err.into());
+        }
+
+        Ok(())
+    }
+
+    /// All entries in the order they were logged, optionally only those
+    /// matching `filter`
+    pub fn entries(&self, filter: impl Fn(&AuditEntry) -> bool) -> Result<Vec<AuditEntry>> {
+        let mut entries = Vec::new();
+
+        self.scan(0, |entry, _| {
+            if filter(&entry) {
+                entries.push(entry);
+            }
+        })?;
+
+        Ok(entries)
+    }
+
+    /// The last `n` entries of a key in the order they were logged, read
+    /// through the index
+    pub fn key_entries(
+        &self,
+        storage: &str,
+        key: &str,
+        n: Option<usize>,
+    ) -> Result<Vec<AuditEntry>> {
+        let mut index = self.index.lock().unwrap();
+
+        let len = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata.len(),
+            Err(err) if err.kind() == ErrorKind::NotFound => 0,
+            Err(err) => return Err(err.into()),
+        };
+        if len < index.len {
+            // the log was replaced
+            *index = Index::default();
+        }
+
+        let Index { len, keys } = &mut *index;
+        *len = self.scan(*len, |entry, range| {
+            keys.entry((entry.storage, entry.key))
+                .or_default()
+                .push(range);
+        })?;
+
+        let ranges = match keys.get(&(storage.to_owned(), key.to_owned())) {
+            Some(ranges) => &ranges[ranges.len().saturating_sub(n.unwrap_or(usize::MAX))..],
+            None => return Ok(Vec::new()),
+        };
+
+        let mut file = File::open(&self.path)?;
+        let mut entries = Vec::with_capacity(ranges.len());
+
+        for range in ranges {
+            let mut bytes = vec![0; (range.end - range.start) as usize];
+            file.seek(SeekFrom::Start(range.start))?;
+            file.read_exact(&mut bytes)?;
+
+            let text = std::str::from_utf8(&bytes).ok().map(str::trim_end);
+
+            match text.and_then(parse_entry) {
+                Some(entry) => entries.push(entry),
+                None => warn!("Skipping changed entry in {}", self.path.display()),
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Read the entries starting at byte `offset` and pass each one with its
+    /// byte range to `found`. Returns the end of the last entry read.
+    fn scan(&self, offset: u64, mut found: impl FnMut(AuditEntry, Range<u64>)) -> Result<u64> {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => BufReader::new(file),
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err.into()),
+        };
+        file.seek(SeekFrom::Start(offset))?;
+
+        // strings can contain line breaks, so an entry may span several
+        // lines, kept with the offsets they start at
+        let mut lines: Vec<(u64, String)> = Vec::new();
+        // the last line that may start an entry after lines a crash cut off
+        let mut restart = 0;
+        let (mut position, mut end) = (offset, offset);
+
+        loop {
+            let mut line = String::new();
+            let read = file.read_line(&mut line)?;
+            if read == 0 {
+                break;
+            }
+
+            let start = position;
+            position += read as u64;
+            if line.ends_with('\n') {
+                line.pop();
+            }
+            if !lines.is_empty() && line.starts_with("[\"") {
+                restart = lines.len();
+            }
+            lines.push((start, line));
+
+            // every entry ends with the end of its array
+            if !lines.last().unwrap().1.ends_with(']') {
+                continue;
+            }
+
+            // only the first and the last possible start are tried, not every
+            // line in between, and only at the end of an array
+            let starts = iter::once(0).chain((restart > 0).then_some(restart));
+            let entry = starts.into_iter().find_map(|first| {
+                let text: Vec<&str> = lines[first..]
+                    .iter()
+                    .map(|(_, line)| line.as_str())
+                    .collect();
+                Some((first, parse_entry(&text.join("\n"))?))
+            });
+
+            if let Some((first, entry)) = entry {
+                if first > 0 {
+                    warn!("Skipping unreadable lines in {}", self.path.display());
+                }
+                found(entry, lines[first].0..position);
+                lines.clear();
+                restart = 0;
+                end = position;
+            }
+        }
+
+        if !lines.is_empty() {
+            warn!("Skipping unreadable end of {}", self.path.display());
+        }
+
+        Ok(end)
+    }
+}
+
+fn parse_entry(text: &str) -> Option<AuditEntry> {
+    text.parse().ok().and_then(AuditEntry::from_value)
+}
+
+/// Whether a file is empty or its last byte is a line break
+fn ends_with_newline(file: &mut File) -> std::io::Result<bool> {
+    if file.metadata()?.len() == 0 {
+        return Ok(true);
+    }
+
+    let mut last = [0];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last)?;
+
+    Ok(last[0] == b'\n')
+}
+
+type Overlay<'a> = HashMap<&'a str, Option<&'a Value>>;
+
+/// Keys `records` change in `storage` with their old and new values
+fn changes(storage: &Storage, records: &[Record]) -> Vec<(String, Option<Value>, Option<Value>)> {
+    // values after the records handled so far, `None` for removed keys
+    let mut current: Overlay = HashMap::new();
+    let mut cleared = false;
+    let mut changes = Vec::new();
+
+    let value = |current: &Overlay, cleared: bool, key: &str| match current.get(key) {
+        Some(value) => value.cloned(),
+        None if cleared => None,
+        None => storage.value(key).cloned(),
+    };
+
+    for record in records {
+        match record {
+            Record::Set(key, new, _) => {
+                changes.push((
+                    key.to_owned(),
+                    value(&current, cleared, key),
+                    Some(new.clone()),
+                ));
+                current.insert(key, Some(new));
+            }
+            Record::Erase(key) => {
+                changes.push((key.to_owned(), value(&current, cleared, key), None));
+                current.insert(key, None);
+            }
+            Record::Clear => {
+                let mut keys: Vec<&str> = current.keys().copied().collect();
+                if !cleared {
+                    keys.extend(storage.data.keys().map(String::as_str));
+                }
+                keys.sort_unstable();
+                keys.dedup();
+
+                for key in keys {
+                    if let Some(old) = value(&current, cleared, key) {
+                        changes.push((key.to_owned(), Some(old), None));
+                    }
+                }
+
+                current.clear();
+                cleared = true;
+            }
+        }
+    }
+
+    changes
+}
+
+impl StoragePool {
+    /// Run `f` with `caller` logged as the author of all changes it makes on
+    /// this thread, e.g. the name and UID of a player
+    pub fn with_caller<T>(&self, caller: &str, f: impl FnOnce() -> T) -> T {
+        let previous = CALLER.with(|current| current.replace(Some(caller.to_owned())));
+        let result = f();
+        CALLER.with(|current| current.replace(previous));
+
+        result
+    }
+
+    /// The last `n` changes of a key, newest first
+    pub fn history(&self, name: &str, key: &str, n: Option<usize>) -> Result<Vec<AuditEntry>> {
+        let history = self.audit()?.key_entries(name, key, n)?;
+
+        Ok(history.into_iter().rev().collect())
+    }
+
+    /// Write the audit log as a single SQF array like
+    /// [`export`](StoragePool::export), optionally only the changes of one
+    /// storage. Returns the number of entries.
+    pub fn export_audit<P: AsRef<Path>>(&self, path: P, storage: Option<&str>) -> Result<usize> {
+        let entries = self
+            .audit()?
+            .entries(|entry| storage.is_none_or(|storage| entry.storage == storage))?;
+
+        let lines: Vec<String> = entries
+            .iter()
+            .map(|entry| format!("    {}", entry.to_value().as_sqf()))
+            .collect();
+
+        let text = if lines.is_empty() {
+            String::from("[]\n")
+        } else {
+            format!("[\n{}\n]\n", lines.join(",\n"))
+        };
+
+        fs::write(path, text)?;
+
+        Ok(entries.len())
+    }
+}
@@ -0,0 +1,158 @@
+//! Inspect and edit storage files while the server is not running
+use anyhow::{bail, Context, Result};
+use arma_storage::{
+    sqf_library, EncryptionKey, KeyFilter, LockMode, StoragePool, Value, Verification,
+};
+use std::{env, fs, path::PathBuf, process};
+
+const USAGE: &str = "\
+Usage: arma-storage-cli [--path <directory>] <command> [arguments]
+
+Commands:
+    list                            List all storage files
+    dump <storage>                  Print all keys and values
+    get <storage> <key>             Print the value of a key
+    set <storage> <key> <value>     Set a key to a value in SQF syntax
+    erase <storage> <key>           Remove a key
+    export <storage> <file>         Write all keys and values as SQF text
+    import <storage> <file>         Replace a storage with SQF text
+    verify [storage...]             Check storage files for damage
+    salvage <storage>               Recover the readable keys of a damaged storage,
+                                    keeping the damaged file as <storage>.damaged
+    sqf <directory>                 Write the SQF function library for missions
+
+The storage directory defaults to ARMA_STORAGE_PATH or arma_storage in the
+current directory.
+Encrypted storages need the key in ARMA_STORAGE_KEY or ARMA_STORAGE_KEY_FILE.
+Storages opened exclusively by a running server can not be accessed.";
+
+fn main() {
+    env_logger::init();
+
+    if let Err(err) = run(env::args().skip(1).collect()) {
+        eprintln!("Error: {:#}", err);
+        process::exit(1);
+    }
+}
+
+fn run(mut args: Vec<String>) -> Result<()> {
+    let mut path = env::var_os("ARMA_STORAGE_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("arma_storage"));
+
+    if args.first().map(String::as_str) == Some("--path") {
+        if args.len() < 2 {
+            bail!("--path needs a directory\n\n{}", USAGE);
+        }
+
+        path = PathBuf::from(args.remove(1));
+        args.remove(0);
+    }
+
+    let mut pool = StoragePool::new(&path);
+    pool.set_key(EncryptionKey::from_env()?);
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        ["list"] => {
+            for name in pool.files_on_disk()? {
+                println!("{}", name);
+            }
+        }
+        ["dump", name] => {
+            load(&pool, name, LockMode::Shared)?;
+
+            for key in pool.keys(name, &KeyFilter::All, 0, None)? {
+                println!("{} = {}", key, pool.get(name, &key)?.as_sqf());
+            }
+        }
+        ["get", name, key] => {
+            load(&pool, name, LockMode::Shared)?;
+
+            println!("{}", pool.get(name, key)?.as_sqf());
+        }
+        ["set", name, key, value] => {
+            let value: Value = value.parse()?;
+
+            load_or_create(&pool, name)?;
+            pool.set(name, key, &value, None)?;
+            pool.write(name)?;
+        }
+        ["erase", name, key] => {
+            load(&pool, name, LockMode::Exclusive)?;
+            pool.erase(name, key)?;
+            pool.write(name)?;
+        }
+        ["export", name, file] => {
+            load(&pool, name, LockMode::Shared)?;
+            pool.export(name, file)?;
+        }
+        ["import", name, file] => {
+            pool.open(name)?;
+            pool.import(name, file)?;
+            pool.write(name)?;
+        }
+        ["verify", names @ ..] => {
+            let names = if names.is_empty() {
+                pool.files_on_disk()?
+            } else {
+                names.iter().map(|name| name.to_string()).collect()
+            };
+
+            let mut failed = 0;
+
+            for name in &names {
+                match pool.verify(name) {
+                    Ok(Verification::Intact { .. }) => println!("{}: ok", name),
+                    Ok(Verification::Damaged(reason)) => {
+                        println!("{}: {}", name, reason);
+                        failed += 1;
+                    }
+                    Err(err) => {
+                        println!("{}: {:#}", name, err);
+                        failed += 1;
+                    }
+                }
+            }
+
+            if failed > 0 {
+                bail!("{} of {} storages are broken", failed, names.len());
+            }
+        }
+        ["salvage", name] => {
+            pool.open_with(name, LockMode::Exclusive)?;
+            let keys = pool.salvage(name)?;
+            pool.write(name)?;
+
+            println!("{}: recovered {} keys", name, keys);
+        }
+        ["sqf", directory] => {
+            for (file, contents) in sqf_library() {
+                let file = PathBuf::from(directory).join(file);
+
+                fs::create_dir_all(file.parent().unwrap())?;
+                fs::write(&file, contents)
+                    .with_context(|| format!("Could not write {}", file.display()))?;
+            }
+        }
+        _ => bail!("{}", USAGE),
+    }
+
+    Ok(())
+}
+
+/// Open and read a storage file
+fn load(pool: &StoragePool, name: &str, mode: LockMode) -> Result<()> {
+    pool.open_with(name, mode)?;
+    pool.read(name)
+        .with_context(|| format!("Could not read storage {}", name))
+}
+
+/// Open a storage and read it if the file exists
+fn load_or_create(pool: &StoragePool, name: &str) -> Result<()> {
+    if pool.files_on_disk()?.iter().any(|file| file == name) {
+        load(pool, name, LockMode::Exclusive)
+    } else {
+        pool.open(name)
+    }
+}
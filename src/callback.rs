@@ -0,0 +1,54 @@
+//! Extension callbacks raising the `ExtensionCallback` mission event handler
+use log::error;
+use std::{
+    ffi::CString,
+    os::raw::{c_char, c_int},
+    sync::Mutex,
+};
+
+/// Function Arma passes to `RVExtensionRegisterCallback`
+pub type Callback = extern "system" fn(*const c_char, *const c_char, *const c_char) -> c_int;
+
+/// Name passed as the first parameter of the event handler
+const NAME: &str = "arma_storage";
+
+/// Raised by the watcher of changes on disk
+pub const STORAGE_CHANGED: &str = "storageChanged";
+
+/// Functions the event handler is raised with and what they are about
+pub const EVENTS: &[(&str, &str)] = &[(
+    STORAGE_CHANGED,
+    "A storage was read again after it changed on disk, the data is its name",
+)];
+
+static CALLBACK: Mutex<Option<Callback>> = Mutex::new(None);
+
+pub fn register(callback: Callback) {
+    *CALLBACK.lock().unwrap() = Some(callback);
+}
+
+/// Raise the event handler with `function` and `data`. Nothing happens before
+/// Arma registered the callback.
+pub fn send(function: &str, data: &str) {
+    let callback = match *CALLBACK.lock().unwrap() {
+        Some(callback) => callback,
+        None => return,
+    };
+
+    let (name, function, data) = match (
+        CString::new(NAME),
+        CString::new(function),
+        CString::new(data),
+    ) {
+        (Ok(name), Ok(function), Ok(data)) => (name, function, data),
+        _ => {
+            error!("Callback {} contains a null byte", function);
+            return;
+        }
+    };
+
+    // Arma returns a negative number if its callback queue is full
+    if callback(name.as_ptr(), function.as_ptr(), data.as_ptr()) < 0 {
+        error!("Arma dropped callback {}", function.to_string_lossy());
+    }
+}
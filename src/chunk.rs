@@ -0,0 +1,111 @@
+//! Results too large for the response buffer Arma passes
+//!
+//! Such a result is split into chunks kept until the script fetched them. The
+//! call returns [`ErrorCodes::Chunked`] with `[id, count, code]`, `code` being
+//! the code of the actual result. `["chunk", id, index]` returns each chunk as
+//! a string and the chunks joined in order are the result.
+//!
+//! Every result is kept by its own id, so scripts fetching results at the
+//! same time do not drop each other's chunks. Results not fetched completely
+//! are dropped after [`PENDING_TIMEOUT`].
+use crate::{error::ErrorCodes, Value};
+use lazy_static::lazy_static;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// How long the chunks of a result are kept
+const PENDING_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Result ids go up to this and start over, staying exact as SQF numbers
+const MAX_ID: u32 = 1 << 24;
+
+struct Pending {
+    chunks: Vec<String>,
+    created: Instant,
+}
+
+lazy_static! {
+    static ref PENDING: Mutex<HashMap<u32, Pending>> = Mutex::new(HashMap::new());
+}
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Make a result fit into a buffer of `size` bytes including the null byte,
+/// splitting it into chunks if it does not
+pub fn fit(code: ErrorCodes, result: String, size: usize) -> (ErrorCodes, String) {
+    if result.len() < size {
+        return (code, result);
+    }
+
+    let chunks = split(&result, size - 1);
+    let id = NEXT_ID
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |id| {
+            Some(id % MAX_ID + 1)
+        })
+        .unwrap();
+
+    let header = Value::Array(vec![
+        Value::Number(id as f32),
+        Value::Number(chunks.len() as f32),
+        Value::Number(code as i32 as f32),
+    ]);
+
+    let mut pending = PENDING.lock().unwrap();
+    pending.retain(|_, pending| pending.created.elapsed() < PENDING_TIMEOUT);
+    pending.insert(
+        id,
+        Pending {
+            chunks,
+            created: Instant::now(),
+        },
+    );
+
+    (ErrorCodes::Chunked, header.as_sqf())
+}
+
+/// Chunk `index` of result `id`. The result is dropped after its last chunk
+/// was fetched.
+pub fn take(id: u32, index: usize) -> Option<String> {
+    let mut pending = PENDING.lock().unwrap();
+    let chunks = &pending.get(&id)?.chunks;
+    let chunk = chunks.get(index)?.clone();
+
+    if index + 1 == chunks.len() {
+        pending.remove(&id);
+    }
+
+    Some(chunk)
+}
+
+/// Split `text` into pieces that are at most `max` bytes long once quoted as
+/// SQF strings
+fn split(text: &str, max: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    // the surrounding quotes
+    let mut len = 2;
+
+    for character in text.chars() {
+        let quoted_len = match character {
+            '"' => 2,
+            character => character.len_utf8(),
+        };
+
+        if len + quoted_len > max {
+            chunks.push(std::mem::take(&mut chunk));
+            len = 2;
+        }
+
+        chunk.push(character);
+        len += quoted_len;
+    }
+
+    chunks.push(chunk);
+
+    chunks
+}
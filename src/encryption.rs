@@ -0,0 +1,102 @@
+//! Encryption of storage files at rest
+//!
+//! Files are encrypted with ChaCha20-Poly1305 using a random nonce for every
+//! write, which is stored in front of the encrypted data. The file header is
+//! authenticated as well, so a changed header is detected like changed data.
+use crate::storage::StorageError;
+use anyhow::{bail, ensure, Context, Result};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use std::{env, fmt, fs, str::FromStr};
+
+const NONCE_SIZE: usize = 12;
+
+/// 256 bit key written as 64 hexadecimal digits
+#[derive(Clone, PartialEq)]
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    /// Key set by the `ARMA_STORAGE_KEY` environment variable or read from the
+    /// file named by `ARMA_STORAGE_KEY_FILE`
+    pub fn from_env() -> Result<Option<Self>> {
+        let key = match (
+            env::var("ARMA_STORAGE_KEY"),
+            env::var_os("ARMA_STORAGE_KEY_FILE"),
+        ) {
+            (Ok(key), _) => key,
+            (Err(_), Some(path)) => fs::read_to_string(&path)
+                .with_context(|| format!("Could not read key file {}", path.to_string_lossy()))?,
+            (Err(_), None) => return Ok(None),
+        };
+
+        key.trim().parse().map(Some)
+    }
+
+    pub(crate) fn encrypt(&self, header: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&self.0));
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+        let encrypted = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: data,
+                    aad: header,
+                },
+            )
+            .ok()
+            .context(StorageError::Serialize)?;
+
+        let mut bytes = nonce.to_vec();
+        bytes.extend(encrypted);
+
+        Ok(bytes)
+    }
+
+    pub(crate) fn decrypt(&self, header: &[u8], bytes: &[u8]) -> Result<Vec<u8>> {
+        ensure!(bytes.len() >= NONCE_SIZE, StorageError::WrongKey);
+
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&self.0));
+        let (nonce, encrypted) = bytes.split_at(NONCE_SIZE);
+
+        cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: encrypted,
+                    aad: header,
+                },
+            )
+            .ok()
+            .context(StorageError::WrongKey)
+    }
+}
+
+impl FromStr for EncryptionKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 64 || !s.is_ascii() {
+            bail!("Encryption key must be 64 hexadecimal digits");
+        }
+
+        let mut key = [0; 32];
+
+        for (byte, digits) in key.iter_mut().zip(s.as_bytes().chunks(2)) {
+            let digits = std::str::from_utf8(digits)?;
+            *byte = u8::from_str_radix(digits, 16)
+                .context("Encryption key must be 64 hexadecimal digits")?;
+        }
+
+        Ok(Self(key))
+    }
+}
+
+/// Never print the key into logs
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
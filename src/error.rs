@@ -1,17 +1,262 @@
-use std::os::raw::c_int;
+use crate::{storage::StorageError, Value};
+use std::{io, os::raw::c_int};
 
-#[derive(Debug, Clone, Copy)]
-pub enum ErrorCodes {
-    Ok = 0,
-    InvalidUtf8 = 1,
-    UnknownFunction = 2,
-    MissingArgument = 10,
-    EmptyArgument = 11,
-    StorageError = 20,
+/// Defines [`ErrorCodes`] with the name and description of every code, so the
+/// enum, its documentation and the `errorCodes` function can not disagree
+macro_rules! error_codes {
+    ($($(#[$attr:meta])* $variant:ident = $code:literal, $name:literal, $description:literal;)*) => {
+        /// Codes returned by every call
+        ///
+        /// | Code | Name | Description |
+        /// | :--: | ---- | ----------- |
+        $(#[doc = concat!("| ", $code, " | `", $name, "` | ", $description, " |")])*
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        pub enum ErrorCodes {
+            $($(#[$attr])* $variant = $code,)*
+        }
+
+        impl ErrorCodes {
+            /// All codes in ascending order
+            pub const ALL: &'static [ErrorCodes] = &[$(ErrorCodes::$variant,)*];
+
+            /// Stable camelCase name of the code
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(ErrorCodes::$variant => $name,)*
+                }
+            }
+
+            pub fn description(self) -> &'static str {
+                match self {
+                    $(ErrorCodes::$variant => $description,)*
+                }
+            }
+        }
+    };
+}
+
+error_codes! {
+    Ok = 0, "ok", "No error";
+    /// The result is empty
+    InvalidUtf8 = 1, "invalidUtf8", "A parameter is not valid UTF-8";
+    /// Identifier `unknownFunction`
+    UnknownFunction = 2, "unknownFunction", "The function passed is unknown";
+    /// The result is `[id, count, code]`, see the `chunk` function
+    Chunked = 3, "chunked", "The result is too large for one call and has to be fetched in chunks";
+    /// Identifier `missingArgument`
+    MissingArgument = 10, "missingArgument", "Missing a required argument";
+    /// Identifier `emptyArgument`
+    EmptyArgument = 11, "emptyArgument", "Argument is empty";
+    /// Identifiers `invalidArgument`, `invalidName`, `invalidImport` and
+    /// `invalidEntries`
+    InvalidArgument = 12, "invalidArgument", "Argument could not be parsed";
+    /// Identifiers `storageError`, `ioError` and `serializeFailed`
+    StorageError = 20, "storageError", "Any other error in the storage";
+    /// Identifier `limitExceeded` with context `[limit, max]`
+    LimitExceeded = 21, "limitExceeded", "A storage limit was exceeded";
+    /// Identifiers `storageNotOpen`, `keyNotFound`, `transactionNotFound` and
+    /// `fileNotFound`
+    NotFound = 22, "notFound", "Something does not exist";
+    /// Identifiers `deserializeFailed`, `checksumMismatch`, `truncated`,
+    /// `unsupportedVersion`, `unsupportedCompression` and
+    /// `unsupportedEncryption`
+    Corrupted = 23, "corrupted", "A storage file is damaged";
+    /// Identifiers `lockedByOtherProcess` and `readOnly`
+    Locked = 24, "locked", "A storage is locked";
+    /// Identifier `permissionDenied`
+    PermissionDenied = 25, "permissionDenied", "Access to a file was denied";
+    /// Identifiers `storageOpen` and `modifiedOnDisk`
+    Conflict = 26, "conflict", "The storage is in a conflicting state";
+    /// Identifiers `keyRequired` and `wrongKey`
+    Encryption = 27, "encryption", "A storage file can not be decrypted";
+    /// Identifiers `typeMismatch` with context `[key, expected, found]`,
+    /// `indexOutOfBounds` with context `[key, index, length]` and
+    /// `auditDisabled`
+    InvalidOperation = 28, "invalidOperation", "The operation does not fit the value";
+}
+
+impl ErrorCodes {
+    pub fn from_code(code: c_int) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|error_code| c_int::from(*error_code) == code)
+    }
+}
+
+impl From<ErrorCodes> for c_int {
+    fn from(code: ErrorCodes) -> Self {
+        code as c_int
+    }
 }
 
-impl Into<c_int> for ErrorCodes {
-    fn into(self) -> c_int {
-        self as c_int
+/// Error returned to scripts as `[code, identifier, message, context]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtensionError {
+    pub code: ErrorCodes,
+    /// Stable camelCase name to branch on, more specific than the code
+    pub identifier: &'static str,
+    /// Human readable description including the cause
+    pub message: String,
+    /// What the error is about, e.g. the missing key, or `""`
+    pub context: Value,
+}
+
+impl ExtensionError {
+    pub fn new(
+        code: ErrorCodes,
+        identifier: &'static str,
+        message: impl Into<String>,
+        context: Value,
+    ) -> Self {
+        Self {
+            code,
+            identifier,
+            message: message.into(),
+            context,
+        }
+    }
+
+    /// Error about the argument `arg_name`, `code` being one of the argument
+    /// error codes
+    pub fn argument(code: ErrorCodes, arg_name: &str) -> Self {
+        let message = match code {
+            ErrorCodes::MissingArgument => format!("Missing argument {}", arg_name),
+            ErrorCodes::EmptyArgument => format!("Argument {} is empty", arg_name),
+            _ => format!("Argument {} could not be parsed", arg_name),
+        };
+
+        Self::new(code, code.name(), message, Value::String(arg_name.into()))
+    }
+
+    pub fn unknown_function(name: &str) -> Self {
+        Self::new(
+            ErrorCodes::UnknownFunction,
+            ErrorCodes::UnknownFunction.name(),
+            format!("Unknown function {}", name),
+            Value::String(name.into()),
+        )
+    }
+
+    pub fn to_value(&self) -> Value {
+        Value::Array(vec![
+            Value::Number(self.code as i32 as f32),
+            Value::String(self.identifier.into()),
+            Value::String(self.message.clone()),
+            self.context.clone(),
+        ])
+    }
+}
+
+impl From<&anyhow::Error> for ExtensionError {
+    fn from(err: &anyhow::Error) -> Self {
+        let message = format!("{:#}", err);
+
+        if let Some(storage_error) = err.downcast_ref::<StorageError>() {
+            return Self::new(
+                storage_error.code(),
+                storage_error.identifier(),
+                message,
+                storage_error.context(),
+            );
+        }
+
+        let io_error = err
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<io::Error>());
+
+        let (code, identifier) = match io_error.map(io::Error::kind) {
+            Some(io::ErrorKind::NotFound) => (ErrorCodes::NotFound, "fileNotFound"),
+            Some(io::ErrorKind::PermissionDenied) => {
+                (ErrorCodes::PermissionDenied, "permissionDenied")
+            }
+            Some(_) => (ErrorCodes::StorageError, "ioError"),
+            None => (ErrorCodes::StorageError, "storageError"),
+        };
+
+        Self::new(code, identifier, message, Value::String(String::new()))
+    }
+}
+
+impl StorageError {
+    pub fn code(&self) -> ErrorCodes {
+        match self {
+            StorageError::StorageIsClosed
+            | StorageError::StorageMissingKey(_)
+            | StorageError::UnknownTransaction(_) => ErrorCodes::NotFound,
+            StorageError::Deserialize
+            | StorageError::UnsupportedVersion(_)
+            | StorageError::UnsupportedCompression(_)
+            | StorageError::UnsupportedEncryption(_)
+            | StorageError::ChecksumMismatch
+            | StorageError::Truncated => ErrorCodes::Corrupted,
+            StorageError::LockedByOtherProcess(_) | StorageError::ReadOnly(_) => ErrorCodes::Locked,
+            StorageError::StorageIsOpen | StorageError::ModifiedOnDisk(_) => ErrorCodes::Conflict,
+            StorageError::KeyRequired | StorageError::WrongKey => ErrorCodes::Encryption,
+            StorageError::TypeMismatch { .. }
+            | StorageError::IndexOutOfBounds { .. }
+            | StorageError::AuditDisabled => ErrorCodes::InvalidOperation,
+            StorageError::InvalidName(_) | StorageError::Import => ErrorCodes::InvalidArgument,
+            StorageError::LimitExceeded { .. } => ErrorCodes::LimitExceeded,
+            StorageError::Serialize => ErrorCodes::StorageError,
+        }
+    }
+
+    /// Stable name of the error for scripts
+    pub fn identifier(&self) -> &'static str {
+        match self {
+            StorageError::StorageIsOpen => "storageOpen",
+            StorageError::StorageIsClosed => "storageNotOpen",
+            StorageError::StorageMissingKey(_) => "keyNotFound",
+            StorageError::Deserialize => "deserializeFailed",
+            StorageError::Serialize => "serializeFailed",
+            StorageError::Import => "invalidImport",
+            StorageError::LockedByOtherProcess(_) => "lockedByOtherProcess",
+            StorageError::ReadOnly(_) => "readOnly",
+            StorageError::ModifiedOnDisk(_) => "modifiedOnDisk",
+            StorageError::LimitExceeded { .. } => "limitExceeded",
+            StorageError::InvalidName(_) => "invalidName",
+            StorageError::UnsupportedVersion(_) => "unsupportedVersion",
+            StorageError::UnsupportedCompression(_) => "unsupportedCompression",
+            StorageError::UnsupportedEncryption(_) => "unsupportedEncryption",
+            StorageError::ChecksumMismatch => "checksumMismatch",
+            StorageError::Truncated => "truncated",
+            StorageError::KeyRequired => "keyRequired",
+            StorageError::WrongKey => "wrongKey",
+            StorageError::AuditDisabled => "auditDisabled",
+            StorageError::TypeMismatch { .. } => "typeMismatch",
+            StorageError::UnknownTransaction(_) => "transactionNotFound",
+            StorageError::IndexOutOfBounds { .. } => "indexOutOfBounds",
+        }
+    }
+
+    /// What the error is about, `""` if nothing
+    pub fn context(&self) -> Value {
+        let string = |string: &str| Value::String(string.into());
+        let number = |number: usize| Value::Number(number as f32);
+
+        match self {
+            StorageError::StorageMissingKey(name)
+            | StorageError::LockedByOtherProcess(name)
+            | StorageError::ReadOnly(name)
+            | StorageError::ModifiedOnDisk(name)
+            | StorageError::InvalidName(name) => string(name),
+            StorageError::LimitExceeded { limit, max } => {
+                Value::Array(vec![string(limit), number(*max)])
+            }
+            StorageError::UnsupportedVersion(byte)
+            | StorageError::UnsupportedCompression(byte)
+            | StorageError::UnsupportedEncryption(byte) => number(usize::from(*byte)),
+            StorageError::TypeMismatch {
+                key,
+                expected,
+                found,
+            } => Value::Array(vec![string(key), string(expected), string(found)]),
+            StorageError::UnknownTransaction(id) => number(*id as usize),
+            StorageError::IndexOutOfBounds { key, index, len } => {
+                Value::Array(vec![string(key), number(*index), number(*len)])
+            }
+            _ => string(""),
+        }
     }
 }
@@ -0,0 +1,166 @@
+//! Storages as text
+//!
+//! A storage is exported as a single SQF array of `[key, value]` pairs which
+//! can be read with `parseSimpleArray`, `call compile` or the value parser.
+//! Files ending in `.sqf` start with a comment naming the storage, which
+//! `parseSimpleArray` does not accept. Sides can only be read with `call compile`.
+use crate::{
+    journal::Record,
+    storage::{is_valid_name, Storage, StorageError, StoragePool},
+    Value,
+};
+use anyhow::{ensure, Context, Result};
+use log::info;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Directory for exports called from SQF
+const EXPORT_DIRECTORY: &str = "export";
+
+impl StoragePool {
+    /// Format all keys and values of a storage as text sorted by key.
+    /// Expired keys are left out.
+    pub fn export_text(&self, name: &str) -> Result<String> {
+        let pairs: Vec<String> = self.with_storage(name, |storage| {
+            let mut keys: Vec<&String> = storage
+                .data
+                .keys()
+                .filter(|key| !storage.is_expired(key))
+                .collect();
+            keys.sort_unstable();
+
+            Ok(keys
+                .into_iter()
+                .map(|key| {
+                    let pair = Value::Array(vec![
+                        Value::String(key.to_owned()),
+                        storage.data[key].clone(),
+                    ]);
+                    format!("    {}", pair.as_sqf())
+                })
+                .collect())
+        })?;
+
+        if pairs.is_empty() {
+            Ok(String::from("[]\n"))
+        } else {
+            Ok(format!("[\n{}\n]\n", pairs.join(",\n")))
+        }
+    }
+
+    /// Replace the data of a storage with text in the format of
+    /// [`export_text`](StoragePool::export_text). `//` comments outside of
+    /// strings are ignored.
+    pub fn import_text(&self, name: &str, text: &str) -> Result<()> {
+        let pairs = match strip_comments(text)
+            .trim()
+            .parse()
+            .context(StorageError::Import)?
+        {
+            Value::Array(pairs) => pairs,
+            _ => return Err(StorageError::Import.into()),
+        };
+
+        let mut data = HashMap::with_capacity(pairs.len());
+
+        for pair in pairs {
+            match pair {
+                Value::Array(mut pair) if pair.len() == 2 => {
+                    let value = pair.pop().unwrap();
+
+                    match pair.pop().unwrap() {
+                        Value::String(key) => data.insert(key, value),
+                        _ => return Err(StorageError::Import.into()),
+                    };
+                }
+                _ => return Err(StorageError::Import.into()),
+            }
+        }
+
+        self.with_storage_mut(name, |storage| {
+            let mut imported = Storage::new(name);
+            imported.set_data(data);
+            self.limits()
+                .check(self.change(storage, &imported), imported.data.values())?;
+
+            self.record(storage, || {
+                let sets = imported
+                    .data
+                    .iter()
+                    .map(|(key, value)| Record::Set(key.to_owned(), value.clone(), None));
+
+                std::iter::once(Record::Clear).chain(sets).collect()
+            })?;
+
+            storage.set_data(imported.data);
+            storage.expiry.clear();
+
+            Ok(())
+        })
+    }
+
+    pub fn export<P: AsRef<Path>>(&self, name: &str, path: P) -> Result<()> {
+        let mut text = self.export_text(name)?;
+
+        if path.as_ref().extension().is_some_and(|ext| ext == "sqf") {
+            text.insert_str(0, &format!("// arma_storage export of {}\n", name));
+        }
+
+        fs::write(&path, text)?;
+
+        info!("Exported storage {} to {}", name, path.as_ref().display());
+
+        Ok(())
+    }
+
+    /// Path of a file in the export directory inside the storage directory
+    pub fn export_path(&self, file: &str) -> Result<PathBuf> {
+        ensure!(
+            is_valid_name(file),
+            StorageError::InvalidName(file.to_owned())
+        );
+
+        let directory = self.path().join(EXPORT_DIRECTORY);
+        fs::create_dir_all(&directory)?;
+
+        Ok(directory.join(file))
+    }
+
+    pub fn import<P: AsRef<Path>>(&self, name: &str, path: P) -> Result<()> {
+        let text = fs::read_to_string(&path)?;
+        self.import_text(name, &text)?;
+
+        info!("Imported storage {} from {}", name, path.as_ref().display());
+
+        Ok(())
+    }
+}
+
+/// Remove `//` comments outside of strings. Quotes inside strings are doubled,
+/// so they leave and enter the string again.
+fn strip_comments(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut quote = None;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, '/') if chars.peek() == Some(&'/') => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+                continue;
+            }
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), c) if c == open => quote = None,
+            _ => {}
+        }
+
+        stripped.push(c);
+    }
+
+    stripped
+}
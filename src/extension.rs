@@ -1,76 +1,213 @@
-use crate::{error::ErrorCodes, storage::StoragePool, Value};
+use crate::{
+    audit::AuditEntry,
+    callback, chunk,
+    encryption::EncryptionKey,
+    error::{ErrorCodes, ExtensionError},
+    format::Verification,
+    limits::Limits,
+    registry::{self, Arg, Args, Command, Syntax},
+    storage::{format_time, KeyFilter, StoragePool},
+    transaction::Operation,
+    Value,
+};
 use lazy_static::lazy_static;
-use log::error;
-use std::sync::RwLock;
+use log::{debug, error};
+use std::{
+    env, path,
+    sync::Once,
+    thread,
+    time::{Duration, Instant},
+};
 
 lazy_static! {
-    static ref STORAGE_POOL: RwLock<StoragePool> = RwLock::new(StoragePool::new("."));
-}
-
-#[derive(PartialEq)]
-enum Function {
-    ErrorCodes,
-    Open,
-    Close,
-    Read,
-    Write,
-    Get,
-    Set,
-    Erase,
-    Exists,
-    GetFiles,
-    Dump,
-}
-
-pub fn ext(function: &str) -> (ErrorCodes, Value) {
-    let function = match function {
-        "errorCodes" => Function::ErrorCodes,
-        _ => {
-            return (
-                ErrorCodes::UnknownFunction,
-                Value::String(function.to_owned()),
-            )
+    static ref STORAGE_POOL: StoragePool = new_pool();
+    /// When the extension was loaded
+    static ref LOADED: Instant = Instant::now();
+}
+
+/// Start counting the uptime reported by `info`
+pub(crate) fn mark_loaded() {
+    lazy_static::initialize(&LOADED);
+}
+
+/// Storage directory inside the working directory if `ARMA_STORAGE_PATH` is
+/// not set. Only storage files belong in there, `getFiles` lists all of them.
+const DEFAULT_PATH: &str = "arma_storage";
+
+/// Create the storage pool configured by environment variables
+fn new_pool() -> StoragePool {
+    let path = env::var_os("ARMA_STORAGE_PATH").unwrap_or_else(|| DEFAULT_PATH.into());
+    if let Err(err) = std::fs::create_dir_all(&path) {
+        error!("Could not create storage directory {:?}: {}", path, err);
+    }
+
+    let mut pool = StoragePool::new(path);
+
+    if let Ok(mode) = env::var("ARMA_STORAGE_LOCK") {
+        match mode.parse() {
+            Ok(mode) => pool.set_lock_mode(mode),
+            Err(_) => error!("Unknown lock mode {}", mode),
         }
-    };
+    }
 
-    match function {
-        Function::ErrorCodes => (
-            ErrorCodes::Ok,
-            Value::Array(vec![
-                Value::Array(vec![Value::Number(0.), Value::String("No Error".into())]),
-                Value::Array(vec![
-                    Value::Number(2.),
-                    Value::String("The function you passed is unknown".into()),
-                ]),
-                Value::Array(vec![
-                    Value::Number(10.),
-                    Value::String("Missing required argument".into()),
-                ]),
-                Value::Array(vec![
-                    Value::Number(11.),
-                    Value::String("Argument is empty".into()),
-                ]),
-                Value::Array(vec![
-                    Value::Number(20.),
-                    Value::String("A storage error occured".into()),
-                ]),
-            ]),
-        ),
-        _ => unreachable!(),
+    if let Ok(compression) = env::var("ARMA_STORAGE_COMPRESSION") {
+        match compression.parse() {
+            Ok(compression) => pool.set_compression(compression),
+            Err(_) => error!("Unknown compression {}", compression),
+        }
+    }
+
+    if let Ok(mode) = env::var("ARMA_STORAGE_CONFLICT") {
+        match mode.parse() {
+            Ok(mode) => pool.set_conflict_mode(mode),
+            Err(_) => error!("Unknown conflict mode {}", mode),
+        }
+    }
+
+    if let Ok(audit) = env::var("ARMA_STORAGE_AUDIT") {
+        match audit.parse() {
+            Ok(audit) => pool.set_audit(audit),
+            Err(_) => error!("Invalid audit setting {}", audit),
+        }
+    }
+
+    if let Ok(journal) = env::var("ARMA_STORAGE_JOURNAL") {
+        match journal.parse() {
+            Ok(journal) => pool.set_journal(journal),
+            Err(_) => error!("Invalid journal setting {}", journal),
+        }
+    }
+
+    match EncryptionKey::from_env() {
+        Ok(key) => pool.set_key(key),
+        Err(err) => error!("Could not load encryption key: {:#}", err),
+    }
+
+    pool.set_limits(Limits {
+        max_keys: limit_var("ARMA_STORAGE_MAX_KEYS"),
+        max_value_size: limit_var("ARMA_STORAGE_MAX_VALUE_SIZE"),
+        max_storage_size: limit_var("ARMA_STORAGE_MAX_STORAGE_SIZE"),
+        max_total_size: limit_var("ARMA_STORAGE_MAX_TOTAL_SIZE"),
+        max_open_storages: limit_var("ARMA_STORAGE_MAX_OPEN"),
+    });
+
+    pool
+}
+
+/// Limit set by an environment variable, unlimited if it is not set
+fn limit_var(name: &str) -> Option<usize> {
+    let limit = env::var(name).ok()?;
+
+    match limit.parse() {
+        Ok(limit) => Some(limit),
+        Err(_) => {
+            error!("Invalid limit {}={}", name, limit);
+            None
+        }
+    }
+}
+
+/// Point the storage pool to a temporary directory before it is first used
+#[cfg(test)]
+pub(crate) fn test_storage_path() {
+    static SET_PATH: Once = Once::new();
+
+    SET_PATH.call_once(|| {
+        let path = env::temp_dir().join("arma_storage_test");
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        env::set_var("ARMA_STORAGE_PATH", path);
+    });
+}
+
+/// How often expired keys are removed from open storages
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+static START_SWEEPER: Once = Once::new();
+
+/// Start a thread removing expired keys so they do not pile up in memory
+fn start_sweeper() {
+    START_SWEEPER.call_once(|| {
+        thread::spawn(|| loop {
+            thread::sleep(SWEEP_INTERVAL);
+            STORAGE_POOL.sweep();
+        });
+    });
+}
+
+static START_WATCHER: Once = Once::new();
+
+/// Start a thread reloading storages changed on disk every
+/// `ARMA_STORAGE_WATCH` seconds. Every reloaded storage raises the
+/// `storageChanged` callback with its name.
+fn start_watcher() {
+    START_WATCHER.call_once(|| {
+        let interval = match env::var("ARMA_STORAGE_WATCH") {
+            Ok(seconds) => match seconds.parse().map(Duration::try_from_secs_f32) {
+                Ok(Ok(interval)) if !interval.is_zero() => interval,
+                _ => {
+                    error!("Invalid watch interval {}", seconds);
+                    return;
+                }
+            },
+            Err(_) => return,
+        };
+
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+
+            let changed = STORAGE_POOL.reload_changed();
+
+            for name in changed {
+                callback::send(callback::STORAGE_CHANGED, &name);
+            }
+        });
+    });
+}
+
+/// Execute a function passed as a single string `function|arg1|arg2|...`.
+/// `||` is a literal `|` inside an argument.
+pub fn ext(input: &str) -> (ErrorCodes, Value) {
+    start_sweeper();
+    start_watcher();
+
+    let parts = split_args(input);
+    let function = parts.first().map_or("", String::as_str);
+    let args = parts.iter().skip(1).map(String::as_str).collect();
+
+    ext_args_std(function, args)
+}
+
+/// Split a single string at every `|` that is not doubled
+fn split_args(input: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '|' if chars.peek() == Some(&'|') => {
+                chars.next();
+                parts.last_mut().unwrap().push('|');
+            }
+            '|' => parts.push(String::new()),
+            c => parts.last_mut().unwrap().push(c),
+        }
     }
+
+    parts
 }
 
 /// Execute a function with arguments
 ///
 pub fn ext_args(function: &str, args: Vec<&str>) -> (ErrorCodes, Value) {
-    if args.is_empty() {
-        return (
-            ErrorCodes::MissingArgument,
-            Value::String("function".into()),
-        );
-    }
+    start_sweeper();
+    start_watcher();
 
     if function.is_empty() {
+        if args.is_empty() {
+            return argument_error(ErrorCodes::MissingArgument, "function");
+        }
+
         ext_args_alt(function, args)
     } else {
         ext_args_std(function, args)
@@ -78,129 +215,883 @@ pub fn ext_args(function: &str, args: Vec<&str>) -> (ErrorCodes, Value) {
 }
 
 pub fn ext_args_std(function_name: &str, args: Vec<&str>) -> (ErrorCodes, Value) {
-    let function = match function_name {
-        "dump" => Function::Dump,
-        "open" => Function::Open,
-        "close" => Function::Close,
-        "read" => Function::Read,
-        "write" => Function::Write,
-        "get" => Function::Get,
-        "set" => Function::Set,
-        "erase" | "eraseKey" => Function::Erase,
-        "exists" => Function::Exists,
-        "getFiles" | "storages" => Function::GetFiles,
-        _ => {
-            return (
-                ErrorCodes::UnknownFunction,
-                Value::String(function_name.into()),
-            )
-        }
-    };
+    respond(registry::call(Syntax::Standard, function_name, &args))
+}
+
+pub fn ext_args_alt(_data: &str, args: Vec<&str>) -> (ErrorCodes, Value) {
+    let function_name = args[0].trim_matches('"');
+
+    respond(registry::call(Syntax::FileXt, function_name, &args[1..]))
+}
+
+/// All commands with their arguments. Storage commands take the storage name
+/// first.
+pub(crate) const COMMANDS: &[Command] = &[
+    Command::new(
+        "help",
+        &[Arg::string("function").optional()],
+        help,
+        "List all commands as [name, aliases, [[argument, type, optional], ...], description] or get one",
+    ),
+    Command::new(
+        "errorCodes",
+        &[],
+        error_codes,
+        "List all error codes as [code, description, name]",
+    ),
+    Command::new(
+        "errorName",
+        &[Arg::number("code")],
+        error_name,
+        "Name of an error code",
+    ),
+    Command::new(
+        "info",
+        &[],
+        info,
+        "Version, configuration, storage directory and uptime as [name, value] pairs",
+    ),
+    Command::new(
+        "chunk",
+        &[Arg::number("id"), Arg::index("index")],
+        chunk,
+        "Part of a result too large for one call",
+    ),
+    Command::new("dump", &[], dump, "Log the arguments").variadic(),
+    Command::new(
+        "open",
+        &[Arg::string("name"), Arg::string("mode").optional()],
+        open,
+        "Open a storage",
+    ),
+    Command::new(
+        "close",
+        &[Arg::string("name")],
+        close,
+        "Close a storage without writing it",
+    ),
+    Command::new(
+        "read",
+        &[Arg::string("name")],
+        read,
+        "Read a storage from its file",
+    ),
+    Command::new(
+        "write",
+        &[Arg::string("name")],
+        write,
+        "Write a storage to its file",
+    ),
+    Command::new(
+        "get",
+        &[Arg::string("name"), Arg::string("key")],
+        get,
+        "Value of a key",
+    ),
+    Command::new(
+        "set",
+        &[
+            Arg::string("name"),
+            Arg::string("key"),
+            Arg::value("value"),
+            Arg::seconds("ttl").optional(),
+        ],
+        set,
+        "Set a key, expiring after ttl seconds",
+    ),
+    Command::new(
+        "erase",
+        &[Arg::string("name"), Arg::string("key")],
+        erase,
+        "Remove a key",
+    )
+    .aliases(&["eraseKey"]),
+    Command::new(
+        "exists",
+        &[Arg::string("name"), Arg::string("key")],
+        exists,
+        "Whether a key exists",
+    ),
+    Command::new("getFiles", &[], get_files, "Names of the open storages")
+        .aliases(&["storages"]),
+    Command::new(
+        "increment",
+        &[
+            Arg::string("name"),
+            Arg::string("key"),
+            Arg::number("amount").optional(),
+        ],
+        increment,
+        "Add to a number and return it",
+    ),
+    Command::new(
+        "decrement",
+        &[
+            Arg::string("name"),
+            Arg::string("key"),
+            Arg::number("amount").optional(),
+        ],
+        decrement,
+        "Subtract from a number and return it",
+    ),
+    Command::new(
+        "push",
+        &[Arg::string("name"), Arg::string("key"), Arg::value("value")],
+        push,
+        "Append to an array and return its length",
+    ),
+    Command::new(
+        "pushUnique",
+        &[Arg::string("name"), Arg::string("key"), Arg::value("value")],
+        push_unique,
+        "Append to an array if missing and return whether it was",
+    ),
+    Command::new(
+        "removeAt",
+        &[Arg::string("name"), Arg::string("key"), Arg::index("index")],
+        remove_at,
+        "Remove an element of an array and return it",
+    ),
+    Command::new(
+        "removeValue",
+        &[Arg::string("name"), Arg::string("key"), Arg::value("value")],
+        remove_value,
+        "Remove a value from an array and return how often it was removed",
+    ),
+    Command::new(
+        "toggle",
+        &[Arg::string("name"), Arg::string("key")],
+        toggle,
+        "Negate a boolean and return it",
+    ),
+    Command::new(
+        "compareAndSet",
+        &[
+            Arg::string("name"),
+            Arg::string("key"),
+            Arg::value("expected"),
+            Arg::value("new"),
+        ],
+        compare_and_set,
+        "Set a key if it has the expected value and return whether it had",
+    ),
+    Command::new(
+        "getMany",
+        &[Arg::string("name"), Arg::array("keys")],
+        get_many,
+        "Values of several keys as [code, value] or error each",
+    ),
+    Command::new(
+        "setMany",
+        &[Arg::string("name"), Arg::array("entries")],
+        set_many,
+        "Set several [key, value] pairs",
+    ),
+    Command::new(
+        "begin",
+        &[Arg::string("name")],
+        begin,
+        "Start a transaction and return its id",
+    ),
+    Command::new(
+        "txSet",
+        &[Arg::transaction(), Arg::string("key"), Arg::value("value")],
+        transaction_set,
+        "Stage setting a key",
+    ),
+    Command::new(
+        "txErase",
+        &[Arg::transaction(), Arg::string("key")],
+        transaction_erase,
+        "Stage removing a key",
+    ),
+    Command::new(
+        "commit",
+        &[Arg::transaction()],
+        commit,
+        "Apply and write all staged changes",
+    ),
+    Command::new(
+        "rollback",
+        &[Arg::transaction()],
+        rollback,
+        "Discard all staged changes",
+    ),
+    Command::new(
+        "keys",
+        &[
+            Arg::string("name"),
+            Arg::index("offset").optional(),
+            Arg::index("limit").optional(),
+        ],
+        keys,
+        "Sorted keys of a storage",
+    ),
+    Command::new(
+        "keysWithPrefix",
+        &[
+            Arg::string("name"),
+            Arg::string("prefix"),
+            Arg::index("offset").optional(),
+            Arg::index("limit").optional(),
+        ],
+        keys_with_prefix,
+        "Sorted keys starting with a prefix",
+    ),
+    Command::new(
+        "keysMatching",
+        &[
+            Arg::string("name"),
+            Arg::string("pattern"),
+            Arg::index("offset").optional(),
+            Arg::index("limit").optional(),
+        ],
+        keys_matching,
+        "Sorted keys matching a glob pattern",
+    ),
+    Command::new(
+        "ttl",
+        &[Arg::string("name"), Arg::string("key")],
+        ttl,
+        "Seconds until a key expires, -1 if never",
+    ),
+    Command::new(
+        "diskFiles",
+        &[],
+        disk_files,
+        "Names of all storage files on disk",
+    ),
+    Command::new(
+        "openStorages",
+        &[],
+        open_storages,
+        "State of every open storage as [name, value] pairs",
+    ),
+    Command::new(
+        "diskStorages",
+        &[],
+        disk_storages,
+        "Names of the storage files on disk that are not open",
+    ),
+    Command::new(
+        "deleteFile",
+        &[Arg::string("name")],
+        delete_file,
+        "Delete the file of a closed storage",
+    ),
+    Command::new(
+        "export",
+        &[Arg::string("name"), Arg::string("file").optional()],
+        export,
+        "Write a storage as SQF text and return the file name",
+    ),
+    Command::new(
+        "import",
+        &[Arg::string("name"), Arg::string("file")],
+        import,
+        "Replace a storage with SQF text",
+    ),
+    Command::new(
+        "stats",
+        &[Arg::string("name")],
+        stats,
+        "Usage and limits as [name, number] pairs",
+    ),
+    Command::new(
+        "compression",
+        &[Arg::string("name"), Arg::string("compression")],
+        compression,
+        "Set the compression of a storage file",
+    ),
+    Command::new(
+        "verify",
+        &[Arg::string("name")],
+        verify,
+        "Check a storage file for damage and return [intact, reason]",
+    ),
+    Command::new(
+        "salvage",
+        &[Arg::string("name")],
+        salvage,
+        "Recover the readable keys of a damaged storage and return their number",
+    ),
+    Command::new(
+        "history",
+        &[
+            Arg::string("name"),
+            Arg::string("key"),
+            Arg::index("n").optional(),
+        ],
+        history,
+        "Last changes of a key from the audit log, newest first",
+    ),
+    Command::new(
+        "exportAudit",
+        &[Arg::string("file"), Arg::string("storage").optional()],
+        export_audit,
+        "Write the audit log as SQF text and return the number of entries",
+    ),
+    Command::new(
+        "as",
+        &[Arg::string("caller"), Arg::string("function")],
+        call_as,
+        "Call a function followed by its arguments, logging caller as the author of its changes",
+    )
+    .variadic(),
+];
+
+pub(crate) type Response = (ErrorCodes, Value);
+
+fn respond(result: Result<Value, Response>) -> Response {
+    match result {
+        Ok(value) => (ErrorCodes::Ok, value),
+        Err(response) => response,
+    }
+}
 
-    if args.is_empty() {
-        return (ErrorCodes::MissingArgument, Value::String("storage".into()));
+fn help(args: &Args) -> Result<Value, Response> {
+    match args.optional(0, Args::string) {
+        Some(name) => registry::lookup(args.syntax, name)
+            .map(Command::to_value)
+            .ok_or_else(|| unknown_function(name)),
+        None => Ok(Value::Array(
+            COMMANDS.iter().map(Command::to_value).collect(),
+        )),
     }
+}
 
-    //let storage: Value = args[0].parse();
+/// `[code, description, name]` of every error code
+fn error_codes(_: &Args) -> Result<Value, Response> {
+    Ok(Value::Array(
+        ErrorCodes::ALL
+            .iter()
+            .map(|code| {
+                Value::Array(vec![
+                    Value::Number(*code as i32 as f32),
+                    Value::String(code.description().into()),
+                    Value::String(code.name().into()),
+                ])
+            })
+            .collect(),
+    ))
+}
+
+fn error_name(args: &Args) -> Result<Value, Response> {
+    let code = args.number(0);
+
+    ErrorCodes::from_code(code as i32)
+        .filter(|_| code.fract() == 0.)
+        .map(|code| Value::String(code.name().into()))
+        .ok_or_else(|| argument_error(ErrorCodes::InvalidArgument, "code"))
+}
 
-    match function {
-        Function::Dump => {
-            eprintln!("[Arma Storage] Dumping Data: {:#?}", args);
-            (ErrorCodes::Ok, Value::String(format!("{:#?}", args)))
+fn info(_: &Args) -> Result<Value, Response> {
+    let pool = &*STORAGE_POOL;
+    let limits = pool.limits();
+    let string = |string: String| Value::String(string);
+    let path = path::absolute(pool.path()).unwrap_or_else(|_| pool.path().to_owned());
+    let compression = pool
+        .compression()
+        .map(|c| c.to_string())
+        .unwrap_or_default();
+
+    Ok(Value::Array(vec![
+        pair("version", string(env!("CARGO_PKG_VERSION").into())),
+        pair("path", string(path.display().to_string())),
+        pair("uptime", Value::Number(LOADED.elapsed().as_secs_f32())),
+        pair("lockMode", string(pool.lock_mode().to_string())),
+        pair("conflictMode", string(pool.conflict_mode().to_string())),
+        pair("compression", string(compression)),
+        pair("journal", Value::Boolean(pool.journal_enabled())),
+        pair("audit", Value::Boolean(pool.audit_enabled())),
+        pair("encrypted", Value::Boolean(pool.is_encrypted())),
+        pair("maxKeys", limit(limits.max_keys)),
+        pair("maxSize", limit(limits.max_storage_size)),
+        pair("maxValueSize", limit(limits.max_value_size)),
+        pair("maxTotalSize", limit(limits.max_total_size)),
+        pair("maxOpenStorages", limit(limits.max_open_storages)),
+    ]))
+}
+
+fn chunk(args: &Args) -> Result<Value, Response> {
+    chunk::take(args.number(0) as u32, args.index(1))
+        .map(Value::String)
+        .ok_or_else(|| argument_error(ErrorCodes::InvalidArgument, "index"))
+}
+
+fn dump(args: &Args) -> Result<Value, Response> {
+    debug!("Dumping data: {:#?}", args.rest(0));
+
+    Ok(Value::String(format!("{:#?}", args.rest(0))))
+}
+
+fn open(args: &Args) -> Result<Value, Response> {
+    let name = args.string(0);
+
+    match args.optional(1, Args::string) {
+        Some(mode) => {
+            let mode = mode
+                .parse()
+                .map_err(|_| argument_error(ErrorCodes::InvalidArgument, "mode"))?;
+
+            STORAGE_POOL.open_with(name, mode)
         }
-        Function::Open
-        | Function::ErrorCodes
-        | Function::Close
-        | Function::Read
-        | Function::Write
-        | Function::Set
-        | Function::Get
-        | Function::Erase
-        | Function::Exists
-        | Function::GetFiles => todo!(),
-        _ => unreachable!(),
+        None => STORAGE_POOL.open(name),
     }
+    .map(|_| Value::Void)
+    .map_err(storage_error)
 }
 
-pub fn ext_args_alt(data: &str, args: Vec<&str>) -> (ErrorCodes, Value) {
-    let function_name = args[0].trim_matches('"');
+fn close(args: &Args) -> Result<Value, Response> {
+    STORAGE_POOL
+        .close(args.string(0))
+        .map(|_| Value::Void)
+        .map_err(storage_error)
+}
 
-    if function_name.is_empty() {
-        return (ErrorCodes::EmptyArgument, Value::String("function".into()));
-    }
+fn read(args: &Args) -> Result<Value, Response> {
+    STORAGE_POOL
+        .read(args.string(0))
+        .map(|_| Value::Void)
+        .map_err(storage_error)
+}
 
-    let function = match function_name {
-        "open" => Function::Open,
-        "close" => Function::Close,
-        "read" => Function::Read,
-        "write" => Function::Write,
-        "get" => Function::Get,
-        "set" => Function::Set,
-        "eraseKey" => Function::Erase,
-        "getFiles" => Function::GetFiles,
-        _ => {
-            return (
-                ErrorCodes::UnknownFunction,
-                Value::String(function_name.into()),
+fn write(args: &Args) -> Result<Value, Response> {
+    STORAGE_POOL
+        .write(args.string(0))
+        .map(|_| Value::Void)
+        .map_err(storage_error)
+}
+
+fn get(args: &Args) -> Result<Value, Response> {
+    STORAGE_POOL
+        .get(args.string(0), args.string(1))
+        .map_err(storage_error)
+}
+
+fn set(args: &Args) -> Result<Value, Response> {
+    let ttl = args.optional(3, Args::seconds);
+
+    STORAGE_POOL
+        .set(args.string(0), args.string(1), &args.value(2), ttl)
+        .map(|_| Value::Void)
+        .map_err(storage_error)
+}
+
+fn erase(args: &Args) -> Result<Value, Response> {
+    STORAGE_POOL
+        .erase(args.string(0), args.string(1))
+        .map(|_| Value::Void)
+        .map_err(storage_error)
+}
+
+fn exists(args: &Args) -> Result<Value, Response> {
+    STORAGE_POOL
+        .exists(args.string(0), args.string(1))
+        .map(Value::Boolean)
+        .map_err(storage_error)
+}
+
+fn get_files(_: &Args) -> Result<Value, Response> {
+    let files = STORAGE_POOL
+        .get_files()
+        .into_iter()
+        .map(Value::String)
+        .collect();
+
+    Ok(Value::Array(files))
+}
+
+fn increment(args: &Args) -> Result<Value, Response> {
+    let amount = args.optional(2, Args::number).unwrap_or(1.);
+
+    STORAGE_POOL
+        .increment(args.string(0), args.string(1), amount)
+        .map_err(storage_error)
+}
+
+fn decrement(args: &Args) -> Result<Value, Response> {
+    let amount = args.optional(2, Args::number).unwrap_or(1.);
+
+    STORAGE_POOL
+        .decrement(args.string(0), args.string(1), amount)
+        .map_err(storage_error)
+}
+
+fn push(args: &Args) -> Result<Value, Response> {
+    STORAGE_POOL
+        .push(args.string(0), args.string(1), &args.value(2))
+        .map(|len| Value::Number(len as f32))
+        .map_err(storage_error)
+}
+
+fn push_unique(args: &Args) -> Result<Value, Response> {
+    STORAGE_POOL
+        .push_unique(args.string(0), args.string(1), &args.value(2))
+        .map(Value::Boolean)
+        .map_err(storage_error)
+}
+
+fn remove_at(args: &Args) -> Result<Value, Response> {
+    STORAGE_POOL
+        .remove_at(args.string(0), args.string(1), args.index(2))
+        .map_err(storage_error)
+}
+
+fn remove_value(args: &Args) -> Result<Value, Response> {
+    STORAGE_POOL
+        .remove_value(args.string(0), args.string(1), &args.value(2))
+        .map(|removed| Value::Number(removed as f32))
+        .map_err(storage_error)
+}
+
+fn toggle(args: &Args) -> Result<Value, Response> {
+    STORAGE_POOL
+        .toggle(args.string(0), args.string(1))
+        .map(Value::Boolean)
+        .map_err(storage_error)
+}
+
+fn compare_and_set(args: &Args) -> Result<Value, Response> {
+    STORAGE_POOL
+        .compare_and_set(
+            args.string(0),
+            args.string(1),
+            &args.value(2),
+            &args.value(3),
+        )
+        .map(Value::Boolean)
+        .map_err(storage_error)
+}
+
+fn get_many(args: &Args) -> Result<Value, Response> {
+    let keys = args
+        .array(1)
+        .into_iter()
+        .map(|key| match key {
+            Value::String(key) => Ok(key),
+            _ => Err(argument_error(ErrorCodes::InvalidArgument, "keys")),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    STORAGE_POOL
+        .get_many(args.string(0), &keys)
+        .map(|values| {
+            Value::Array(
+                values
+                    .into_iter()
+                    .map(|value| status(value.map_err(|err| ExtensionError::from(&err))))
+                    .collect(),
             )
-        }
-    };
+        })
+        .map_err(storage_error)
+}
 
-    if args.len() < 2 {
-        return (ErrorCodes::MissingArgument, Value::String("name".into()));
-    }
+fn set_many(args: &Args) -> Result<Value, Response> {
+    // all entries are checked first, so either every key is set or none
+    let mut valid = Vec::new();
+    let mut malformed = 0;
+    let statuses: Vec<Value> = args
+        .array(1)
+        .into_iter()
+        .map(|entry| {
+            let result = match entry {
+                Value::Array(mut pair) if pair.len() == 2 => match pair.remove(0) {
+                    Value::String(key) if key.is_empty() => {
+                        Err(ExtensionError::argument(ErrorCodes::EmptyArgument, "key"))
+                    }
+                    Value::String(key) => {
+                        valid.push((key, pair.remove(0)));
+                        Ok(Value::Void)
+                    }
+                    _ => Err(ExtensionError::argument(ErrorCodes::InvalidArgument, "key")),
+                },
+                _ => Err(ExtensionError::argument(
+                    ErrorCodes::InvalidArgument,
+                    "entry",
+                )),
+            };
 
-    let name = args[1].trim_matches('"');
+            if result.is_err() {
+                malformed += 1;
+            }
+
+            status(result)
+        })
+        .collect();
+
+    if malformed > 0 {
+        let err = ExtensionError::new(
+            ErrorCodes::InvalidArgument,
+            "invalidEntries",
+            format!(
+                "{} of {} entries are malformed, no key was set",
+                malformed,
+                statuses.len()
+            ),
+            Value::Array(statuses),
+        );
 
-    if name.is_empty() {
-        return (ErrorCodes::EmptyArgument, Value::String("name".into()));
+        return Err(error_response(err));
     }
 
-    match function {
-        Function::Open => match STORAGE_POOL.write().unwrap().open(name) {
-            Ok(_) => (ErrorCodes::Ok, Value::Void),
-            Err(err) => {
-                error!("Could not open storage: {:?}", err);
-                (
-                    ErrorCodes::StorageError,
-                    Value::String(format!("Error: {:?}", err)),
-                )
-            }
-        },
-        Function::Close => match STORAGE_POOL.write().unwrap().close(name) {
-            Ok(_) => (ErrorCodes::Ok, Value::Void),
-            Err(err) => {
-                error!("Could not close storage: {:?}", err);
-                (
-                    ErrorCodes::StorageError,
-                    Value::String(format!("Error: {:?}", err)),
-                )
-            }
-        },
-        Function::Read => match STORAGE_POOL.write().unwrap().read(name) {
-            Ok(_) => (ErrorCodes::Ok, Value::Void),
-            Err(err) => {
-                error!("Could not read storage: {:?}", err);
-                (
-                    ErrorCodes::StorageError,
-                    Value::String(format!("Error: {:?}", err)),
-                )
-            }
-        },
-        Function::Write => match STORAGE_POOL.write().unwrap().write(name) {
-            Ok(_) => (ErrorCodes::Ok, Value::Void),
-            Err(err) => {
-                error!("Could not write storage: {:?}", err);
-                (
-                    ErrorCodes::StorageError,
-                    Value::String(format!("Error: {:?}", err)),
-                )
-            }
-        },
-        Function::Get => todo!(),
-        Function::Set => todo!(),
-        Function::Erase => todo!(),
-        Function::GetFiles => todo!(),
-        _ => unreachable!(),
+    STORAGE_POOL
+        .set_many(args.string(0), &valid)
+        .map(|_| Value::Array(statuses))
+        .map_err(storage_error)
+}
+
+fn begin(args: &Args) -> Result<Value, Response> {
+    STORAGE_POOL
+        .begin(args.string(0))
+        .map(|id| Value::Number(id as f32))
+        .map_err(storage_error)
+}
+
+fn transaction_set(args: &Args) -> Result<Value, Response> {
+    let operation = Operation::Set(args.string(1).to_owned(), args.value(2));
+
+    STORAGE_POOL
+        .stage(args.transaction(0), operation)
+        .map(|_| Value::Void)
+        .map_err(storage_error)
+}
+
+fn transaction_erase(args: &Args) -> Result<Value, Response> {
+    let operation = Operation::Erase(args.string(1).to_owned());
+
+    STORAGE_POOL
+        .stage(args.transaction(0), operation)
+        .map(|_| Value::Void)
+        .map_err(storage_error)
+}
+
+fn commit(args: &Args) -> Result<Value, Response> {
+    STORAGE_POOL
+        .commit(args.transaction(0))
+        .map(|_| Value::Void)
+        .map_err(storage_error)
+}
+
+fn rollback(args: &Args) -> Result<Value, Response> {
+    STORAGE_POOL
+        .rollback(args.transaction(0))
+        .map(|_| Value::Void)
+        .map_err(storage_error)
+}
+
+fn keys(args: &Args) -> Result<Value, Response> {
+    list_keys(args, KeyFilter::All, 1)
+}
+
+fn keys_with_prefix(args: &Args) -> Result<Value, Response> {
+    list_keys(args, KeyFilter::Prefix(args.string(1).to_owned()), 2)
+}
+
+fn keys_matching(args: &Args) -> Result<Value, Response> {
+    list_keys(args, KeyFilter::Glob(args.string(1).to_owned()), 2)
+}
+
+/// Keys matching `filter` with offset and limit at `next` and after
+fn list_keys(args: &Args, filter: KeyFilter, next: usize) -> Result<Value, Response> {
+    let offset = args.optional(next, Args::index).unwrap_or(0);
+    let limit = args.optional(next + 1, Args::index);
+
+    STORAGE_POOL
+        .keys(args.string(0), &filter, offset, limit)
+        .map(|keys| Value::Array(keys.into_iter().map(Value::String).collect()))
+        .map_err(storage_error)
+}
+
+fn ttl(args: &Args) -> Result<Value, Response> {
+    STORAGE_POOL
+        .ttl(args.string(0), args.string(1))
+        .map(|ttl| Value::Number(ttl.map_or(-1., |ttl| ttl.as_secs_f32())))
+        .map_err(storage_error)
+}
+
+fn disk_files(_: &Args) -> Result<Value, Response> {
+    STORAGE_POOL
+        .files_on_disk()
+        .map(|files| Value::Array(files.into_iter().map(Value::String).collect()))
+        .map_err(storage_error)
+}
+
+fn open_storages(_: &Args) -> Result<Value, Response> {
+    let time = |millis: Option<u64>| Value::String(millis.map(format_time).unwrap_or_default());
+
+    let storages = STORAGE_POOL
+        .storage_info()
+        .into_iter()
+        .map(|info| {
+            Value::Array(vec![
+                pair("name", Value::String(info.name)),
+                pair("keys", Value::Number(info.keys as f32)),
+                pair("size", Value::Number(info.size as f32)),
+                pair("memory", Value::Number(info.memory as f32)),
+                pair("dirty", Value::Boolean(info.dirty)),
+                pair("lockMode", Value::String(info.lock_mode.to_string())),
+                pair("lastRead", time(info.last_read)),
+                pair("lastWrite", time(info.last_write)),
+            ])
+        })
+        .collect();
+
+    Ok(Value::Array(storages))
+}
+
+fn disk_storages(_: &Args) -> Result<Value, Response> {
+    STORAGE_POOL
+        .closed_files_on_disk()
+        .map(|files| Value::Array(files.into_iter().map(Value::String).collect()))
+        .map_err(storage_error)
+}
+
+fn delete_file(args: &Args) -> Result<Value, Response> {
+    STORAGE_POOL
+        .delete(args.string(0))
+        .map(|_| Value::Void)
+        .map_err(storage_error)
+}
+
+fn export(args: &Args) -> Result<Value, Response> {
+    let name = args.string(0);
+    let file = match args.optional(1, Args::string) {
+        Some(file) => file.to_owned(),
+        None => format!("{}.sqf", name),
+    };
+
+    let pool = &*STORAGE_POOL;
+    pool.export_path(&file)
+        .and_then(|path| pool.export(name, path))
+        .map(|_| Value::String(file))
+        .map_err(storage_error)
+}
+
+fn import(args: &Args) -> Result<Value, Response> {
+    let pool = &*STORAGE_POOL;
+    pool.export_path(args.string(1))
+        .and_then(|path| pool.import(args.string(0), path))
+        .map(|_| Value::Void)
+        .map_err(storage_error)
+}
+
+fn stats(args: &Args) -> Result<Value, Response> {
+    let stats = STORAGE_POOL.stats(args.string(0)).map_err(storage_error)?;
+    let limits = STORAGE_POOL.limits();
+
+    Ok(Value::Array(vec![
+        pair("keys", Value::Number(stats.keys as f32)),
+        pair("maxKeys", limit(limits.max_keys)),
+        pair("size", Value::Number(stats.size as f32)),
+        pair("maxSize", limit(limits.max_storage_size)),
+        pair("maxValueSize", limit(limits.max_value_size)),
+        pair("totalSize", Value::Number(stats.total_size as f32)),
+        pair("maxTotalSize", limit(limits.max_total_size)),
+        pair("openStorages", Value::Number(stats.open_storages as f32)),
+        pair("maxOpenStorages", limit(limits.max_open_storages)),
+    ]))
+}
+
+fn compression(args: &Args) -> Result<Value, Response> {
+    let compression = args
+        .string(1)
+        .parse()
+        .map_err(|_| argument_error(ErrorCodes::InvalidArgument, "compression"))?;
+
+    STORAGE_POOL
+        .set_storage_compression(args.string(0), Some(compression))
+        .map(|_| Value::Void)
+        .map_err(storage_error)
+}
+
+fn verify(args: &Args) -> Result<Value, Response> {
+    let (intact, detail) = match STORAGE_POOL.verify(args.string(0)).map_err(storage_error)? {
+        Verification::Intact { .. } => (true, String::new()),
+        Verification::Damaged(reason) => (false, reason),
+    };
+
+    Ok(Value::Array(vec![
+        Value::Boolean(intact),
+        Value::String(detail),
+    ]))
+}
+
+fn salvage(args: &Args) -> Result<Value, Response> {
+    STORAGE_POOL
+        .salvage(args.string(0))
+        .map(|keys| Value::Number(keys as f32))
+        .map_err(storage_error)
+}
+
+fn history(args: &Args) -> Result<Value, Response> {
+    let n = args.optional(2, Args::index);
+
+    STORAGE_POOL
+        .history(args.string(0), args.string(1), n)
+        .map(|history| Value::Array(history.iter().map(AuditEntry::to_value).collect()))
+        .map_err(storage_error)
+}
+
+fn export_audit(args: &Args) -> Result<Value, Response> {
+    let storage = args.optional(1, Args::string);
+
+    let pool = &*STORAGE_POOL;
+    pool.export_path(args.string(0))
+        .and_then(|path| pool.export_audit(path, storage))
+        .map(|entries| Value::Number(entries as f32))
+        .map_err(storage_error)
+}
+
+/// Run the function following the caller tag, logging the tag with all
+/// changes the function makes
+fn call_as(args: &Args) -> Result<Value, Response> {
+    let name = args.string(1);
+    let command = registry::lookup(args.syntax, name)
+        .filter(|command| command.name != "as")
+        .ok_or_else(|| unknown_function(name))?;
+
+    STORAGE_POOL.with_caller(args.string(0), || command.run(args.syntax, args.rest(2)))
+}
+
+fn storage_error(err: anyhow::Error) -> Response {
+    error!("Storage function failed: {:?}", err);
+
+    error_response(ExtensionError::from(&err))
+}
+
+pub(crate) fn argument_error(code: ErrorCodes, arg_name: &str) -> Response {
+    error_response(ExtensionError::argument(code, arg_name))
+}
+
+pub(crate) fn unknown_function(name: &str) -> Response {
+    error_response(ExtensionError::unknown_function(name))
+}
+
+fn error_response(err: ExtensionError) -> Response {
+    (err.code, err.to_value())
+}
+
+/// A limit as a number, `-1` if unlimited
+fn limit(limit: Option<usize>) -> Value {
+    Value::Number(limit.map_or(-1., |limit| limit as f32))
+}
+
+/// Named entry of a result that can be turned into a hashmap with
+/// `createHashMapFromArray`
+fn pair(name: &str, value: Value) -> Value {
+    Value::Array(vec![Value::String(name.into()), value])
+}
+
+/// Per-key status used by functions operating on multiple keys, `[0, value]`,
+/// `[0]` if there is no value, or the error
+fn status(result: Result<Value, ExtensionError>) -> Value {
+    let ok = Value::Number(ErrorCodes::Ok as i32 as f32);
+
+    match result {
+        // nothing printed in an array can not be parsed
+        Ok(Value::Void) => Value::Array(vec![ok]),
+        Ok(value) => Value::Array(vec![ok, value]),
+        Err(err) => err.to_value(),
     }
 }
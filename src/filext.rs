@@ -0,0 +1,145 @@
+//! Compatibility with [`FileXT`][FileXT]
+//!
+//! The alternative syntax of `callExtension` is the one used by FileXT, so
+//! missions calling the FileXT functions below work with this extension.
+//! FileXT functions map onto the [`StoragePool`][crate::storage::StoragePool] like this:
+//!
+//! | FileXT | Storage Pool |
+//! | ------ | ------------ |
+//! | `open`, `close`, `read`, `write` | the storage function with the same name |
+//! | `get`, `set` | get and set a key |
+//! | `eraseKey` | erase a key |
+//! | `getFiles` | list the storage files on disk, open or not |
+//! | `deleteFile` | delete a storage file from disk |
+//!
+//! All other functions are called by the same names as with the standard
+//! syntax, only `getFiles` means something else.
+//!
+//! This is not a complete drop-in layer yet. FileXT's error strings and
+//! return shapes are not reproduced: errors come back as this extension's
+//! `[code, identifier, message, context]`, so missions that compare FileXT's
+//! error strings have to check the identifier instead. The conformance test
+//! in `tests/alternative_syntax.rs` replays
+//! `tests/arma_storage_test_alternative_syntax.sqf` only, as FileXT's own test
+//! mission is not part of this repository.
+//!
+//! [FileXT]: https://github.com/Vindicta-Team/FileXT
+
+/// Name of the command a function called with the FileXT compatible syntax
+/// runs
+pub(crate) fn command_name(name: &str) -> &str {
+    match name {
+        // FileXT lists the files on disk, not the open storages
+        "getFiles" => "diskFiles",
+        name => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::ErrorCodes,
+        extension::{ext_args, test_storage_path},
+        Value,
+    };
+
+    /// Call with the alternative syntax. Arguments have to be stringified
+    /// like Arma does.
+    fn call(args: &[&str]) -> (ErrorCodes, Value) {
+        test_storage_path();
+
+        ext_args("", args.to_vec())
+    }
+
+    fn error(
+        code: ErrorCodes,
+        identifier: &str,
+        message: &str,
+        context: &str,
+    ) -> (ErrorCodes, Value) {
+        (
+            code,
+            Value::Array(vec![
+                Value::Number(code as i32 as f32),
+                Value::String(identifier.into()),
+                Value::String(message.into()),
+                Value::String(context.into()),
+            ]),
+        )
+    }
+
+    /// The usual FileXT round trip of a file
+    #[test]
+    fn filext_round_trip() {
+        let name = r#""filext_round_trip""#;
+
+        assert_eq!(call(&[r#""open""#, name]).0, ErrorCodes::Ok);
+        assert_eq!(
+            call(&[r#""set""#, name, r#""money""#, "100"]),
+            (ErrorCodes::Ok, Value::Void)
+        );
+        assert_eq!(
+            call(&[r#""set""#, name, r#""gear""#, r#"["arifle_MX_F",[1,2]]"#]),
+            (ErrorCodes::Ok, Value::Void)
+        );
+        assert_eq!(call(&[r#""write""#, name]).0, ErrorCodes::Ok);
+        assert_eq!(call(&[r#""close""#, name]).0, ErrorCodes::Ok);
+
+        assert_eq!(call(&[r#""open""#, name]).0, ErrorCodes::Ok);
+        assert_eq!(call(&[r#""read""#, name]).0, ErrorCodes::Ok);
+        assert_eq!(
+            call(&[r#""get""#, name, r#""money""#]),
+            (ErrorCodes::Ok, Value::Number(100.))
+        );
+        assert_eq!(
+            call(&[r#""get""#, name, r#""gear""#]),
+            (
+                ErrorCodes::Ok,
+                Value::Array(vec![
+                    Value::String("arifle_MX_F".into()),
+                    Value::Array(vec![Value::Number(1.), Value::Number(2.)]),
+                ])
+            )
+        );
+
+        assert_eq!(
+            call(&[r#""eraseKey""#, name, r#""money""#]),
+            (ErrorCodes::Ok, Value::Void)
+        );
+        assert_eq!(
+            call(&[r#""get""#, name, r#""money""#]),
+            error(
+                ErrorCodes::NotFound,
+                "keyNotFound",
+                "Storage has no key money",
+                "money"
+            )
+        );
+
+        let (code, files) = call(&[r#""getFiles""#]);
+        assert_eq!(code, ErrorCodes::Ok);
+        assert!(match files {
+            Value::Array(files) => files.contains(&Value::String("filext_round_trip".into())),
+            _ => false,
+        });
+
+        assert_eq!(call(&[r#""close""#, name]).0, ErrorCodes::Ok);
+        assert_eq!(
+            call(&[r#""deleteFile""#, name]),
+            (ErrorCodes::Ok, Value::Void)
+        );
+
+        let (_, files) = call(&[r#""getFiles""#]);
+        assert!(match files {
+            Value::Array(files) => !files.contains(&Value::String("filext_round_trip".into())),
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn rejects_paths_as_names() {
+        let (code, _) = call(&[r#""open""#, r#""../escape""#]);
+
+        assert_eq!(code, ErrorCodes::InvalidArgument);
+    }
+}
@@ -0,0 +1,477 @@
+//! On-disk format of a storage file
+//!
+//! A file starts with [`MAGIC`] and a format version. Since version 2 a byte
+//! naming the [`Compression`] follows, since version 3 another one telling
+//! whether the file is encrypted and since version 4 a CRC-32 checksum of the
+//! rest of the file. Since version 5 the encryption also covers the file name,
+//! so encrypted files can not be swapped between storages. Then comes the bincode encoded data and expiry
+//! timestamps, first compressed and then encrypted if requested. Version 1
+//! files are neither compressed nor encrypted. Files without the
+//! header were written by older versions and only contain the bincode encoded
+//! data.
+use crate::{
+    encryption::EncryptionKey,
+    storage::{Storage, StorageError},
+    watch::FileStamp,
+    Value,
+};
+use anyhow::{ensure, Context, Result};
+use bincode::{deserialize, serialize, Options};
+use log::warn;
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    fmt,
+    fs::{self, File},
+    io::{Read, Write},
+    path::Path,
+    str::FromStr,
+};
+
+/// Suffix of the file a storage is written to before it replaces the old one
+pub const TEMP_SUFFIX: &str = ".tmp";
+
+const MAGIC: &[u8; 4] = b"ARST";
+const VERSION: u8 = 5;
+
+/// Bytes bincode uses for the length of a map
+const MAP_LEN_SIZE: usize = 8;
+
+/// zstd level trading speed for size, 3 is the default of the zstd tool
+const ZSTD_LEVEL: i32 = 3;
+
+/// Compression of the data in a storage file
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    /// Smaller files
+    Zstd,
+    /// Faster reads and writes
+    Lz4,
+}
+
+impl Compression {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Compression::None),
+            1 => Some(Compression::Zstd),
+            2 => Some(Compression::Lz4),
+            _ => None,
+        }
+    }
+
+    fn as_byte(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Zstd => 1,
+            Compression::Lz4 => 2,
+        }
+    }
+
+    fn compress(self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(bytes),
+            Compression::Zstd => Ok(zstd::encode_all(bytes.as_slice(), ZSTD_LEVEL)?),
+            Compression::Lz4 => Ok(lz4_flex::compress_prepend_size(&bytes)),
+        }
+    }
+
+    fn decompress(self, bytes: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(bytes.to_vec()),
+            Compression::Zstd => zstd::decode_all(bytes).context(StorageError::Deserialize),
+            Compression::Lz4 => {
+                lz4_flex::decompress_size_prepended(bytes).context(StorageError::Deserialize)
+            }
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Compression::None),
+            "zstd" => Ok(Compression::Zstd),
+            "lz4" => Ok(Compression::Lz4),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Compression::None => "none",
+            Compression::Zstd => "zstd",
+            Compression::Lz4 => "lz4",
+        })
+    }
+}
+
+pub type Data = HashMap<String, Value>;
+/// Unix timestamps in milliseconds after which a key is expired
+pub type Expiry = HashMap<String, u64>;
+
+/// Everything stored in a storage file
+#[derive(Default)]
+pub struct Contents {
+    pub data: Data,
+    pub expiry: Expiry,
+    pub compression: Compression,
+}
+
+/// Read a storage file. `key` is only needed for encrypted files.
+pub fn read_file(path: &Path, key: Option<&EncryptionKey>) -> Result<(Contents, FileStamp)> {
+    let (bytes, stamp) = FileStamp::read(path)?;
+    let contents = decode(&bytes, path, key)?;
+
+    Ok((contents, stamp))
+}
+
+/// Outcome of checking a storage file
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verification {
+    /// The file can be read and holds this many keys
+    Intact { keys: usize },
+    /// The file can not be read for the given reason
+    Damaged(String),
+}
+
+/// Check a storage file without reading it into a storage. A missing key is
+/// an error, not damage.
+pub fn verify_file(path: &Path, key: Option<&EncryptionKey>) -> Result<Verification> {
+    let bytes = fs::read(path)?;
+
+    match decode(&bytes, path, key) {
+        Ok(contents) => Ok(Verification::Intact {
+            keys: contents.data.len(),
+        }),
+        Err(err) => match err.downcast_ref() {
+            Some(StorageError::KeyRequired) => Err(err),
+            _ => Ok(Verification::Damaged(format!("{:#}", err))),
+        },
+    }
+}
+
+/// Read as much as possible from a damaged storage file. Keys whose bytes
+/// are damaged are lost, all others are kept. Encrypted files can only be
+/// salvaged if the encrypted data is intact and lz4 compressed files only if
+/// the compressed data is.
+pub fn salvage_file(path: &Path, key: Option<&EncryptionKey>) -> Result<(Contents, FileStamp)> {
+    let (bytes, stamp) = FileStamp::read(path)?;
+
+    let header = match Header::parse(&bytes)? {
+        Some(header) => header,
+        None => {
+            let (data, _) = salvage_entries(&bytes);
+
+            return Ok((
+                Contents {
+                    data,
+                    expiry: Expiry::new(),
+                    compression: Compression::None,
+                },
+                stamp,
+            ));
+        }
+    };
+
+    let body = header.decrypt(&bytes, path, key)?;
+    let body = match header.compression {
+        Compression::Zstd => {
+            // keep everything decoded before the damage
+            let mut decoded = Vec::new();
+            let _ = zstd::Decoder::new(body.as_slice())
+                .and_then(|mut decoder| decoder.read_to_end(&mut decoded));
+            decoded
+        }
+        compression => compression.decompress(&body).unwrap_or_default(),
+    };
+
+    let (data, expiry) = salvage_entries(&body);
+
+    Ok((
+        Contents {
+            data,
+            expiry,
+            compression: header.compression,
+        },
+        stamp,
+    ))
+}
+
+/// Header of a file written by a version with [`MAGIC`]
+struct Header {
+    version: u8,
+    compression: Compression,
+    encrypted: bool,
+    checksum: Option<u32>,
+    /// Header bytes covered by the checksum and the encryption, all but the
+    /// checksum itself
+    authenticated: usize,
+    len: usize,
+}
+
+impl Header {
+    /// `None` for files written before the header was introduced
+    fn parse(bytes: &[u8]) -> Result<Option<Self>> {
+        let version = match bytes.strip_prefix(MAGIC) {
+            Some(rest) => *rest.first().context(StorageError::Truncated)?,
+            None => return Ok(None),
+        };
+
+        let authenticated = match version {
+            1 => MAGIC.len() + 1,
+            2 => MAGIC.len() + 2,
+            3..=5 => MAGIC.len() + 3,
+            _ => return Err(StorageError::UnsupportedVersion(version).into()),
+        };
+        let len = match version {
+            4 | 5 => authenticated + 4,
+            _ => authenticated,
+        };
+        ensure!(bytes.len() >= len, StorageError::Truncated);
+
+        let compression = match bytes[..authenticated].get(MAGIC.len() + 1) {
+            Some(&byte) => {
+                Compression::from_byte(byte).context(StorageError::UnsupportedCompression(byte))?
+            }
+            None => Compression::None,
+        };
+
+        let encrypted = match bytes[..authenticated].get(MAGIC.len() + 2) {
+            None | Some(0) => false,
+            Some(1) => true,
+            Some(&byte) => return Err(StorageError::UnsupportedEncryption(byte).into()),
+        };
+
+        let checksum = match version {
+            4 | 5 => Some(u32::from_le_bytes(
+                bytes[authenticated..len].try_into().unwrap(),
+            )),
+            _ => None,
+        };
+
+        Ok(Some(Header {
+            version,
+            compression,
+            encrypted,
+            checksum,
+            authenticated,
+            len,
+        }))
+    }
+
+    fn verify(&self, bytes: &[u8]) -> Result<()> {
+        if let Some(expected) = self.checksum {
+            let found = checksum(&bytes[..self.authenticated], &bytes[self.len..]);
+            ensure!(found == expected, StorageError::ChecksumMismatch);
+        }
+
+        Ok(())
+    }
+
+    /// The still compressed data following the header
+    fn decrypt(&self, bytes: &[u8], path: &Path, key: Option<&EncryptionKey>) -> Result<Vec<u8>> {
+        let body = &bytes[self.len..];
+
+        if self.encrypted {
+            let key = key.context(StorageError::KeyRequired)?;
+            let header = &bytes[..self.authenticated];
+
+            match self.version {
+                3 | 4 => key.decrypt(header, body),
+                _ => key.decrypt(&associated_data(header, path), body),
+            }
+        } else {
+            Ok(body.to_vec())
+        }
+    }
+}
+
+fn checksum(header: &[u8], body: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(header);
+    hasher.update(body);
+    hasher.finalize()
+}
+
+/// Associated data of an encrypted file: its header and the file name, so
+/// the file can not be moved to another storage
+fn associated_data(header: &[u8], path: &Path) -> Vec<u8> {
+    let name = path.file_name().unwrap_or_default();
+
+    [header, name.to_string_lossy().as_bytes()].concat()
+}
+
+fn decode(bytes: &[u8], path: &Path, key: Option<&EncryptionKey>) -> Result<Contents> {
+    let header = Header::parse(bytes)?;
+
+    if key.is_some() && !header.as_ref().is_some_and(|header| header.encrypted) {
+        warn!(
+            "Storage file {} is not encrypted although a key is set",
+            path.display()
+        );
+    }
+
+    let header = match header {
+        Some(header) => header,
+        None => {
+            return Ok(Contents {
+                data: deserialize(bytes).context(StorageError::Deserialize)?,
+                expiry: Expiry::new(),
+                compression: Compression::None,
+            })
+        }
+    };
+
+    header.verify(bytes)?;
+    let body = header
+        .compression
+        .decompress(&header.decrypt(bytes, path, key)?)?;
+    let (data, expiry) = deserialize(&body).context(StorageError::Deserialize)?;
+
+    Ok(Contents {
+        data,
+        expiry,
+        compression: header.compression,
+    })
+}
+
+/// Decode key/value pairs one by one, skipping bytes that do not decode
+/// until the next pair does. The expiry timestamps following the data are
+/// kept if they are intact.
+fn salvage_entries(bytes: &[u8]) -> (Data, Expiry) {
+    let mut data = Data::new();
+    let mut expiry = Expiry::new();
+
+    // the number of pairs is not trusted, damage may have changed it
+    let mut position = MAP_LEN_SIZE.min(bytes.len());
+    // bytes inside a damaged pair can decode by chance, so after damage a
+    // pair only counts if another one or the end of the data follows
+    let mut in_sync = true;
+
+    while position < bytes.len() {
+        match find(&bytes[position..]) {
+            Found::Expiry(found) => {
+                expiry = found;
+                break;
+            }
+            Found::Pair(key, value, len) => {
+                let next = position + len;
+
+                if in_sync || next == bytes.len() || !matches!(find(&bytes[next..]), Found::Nothing)
+                {
+                    data.insert(key, value);
+                    position = next;
+                    in_sync = true;
+                } else {
+                    position += 1;
+                }
+            }
+            Found::Nothing => {
+                position += 1;
+                in_sync = false;
+            }
+        }
+    }
+
+    expiry.retain(|key, _| data.contains_key(key));
+
+    (data, expiry)
+}
+
+/// What damaged data starts with
+enum Found {
+    /// A key/value pair and its length in bytes
+    Pair(String, Value, usize),
+    /// The expiry map, the last thing in the data
+    Expiry(Expiry),
+    Nothing,
+}
+
+fn find(bytes: &[u8]) -> Found {
+    let options = bincode::options()
+        .with_fixint_encoding()
+        .allow_trailing_bytes();
+
+    // pairs can also decode from the start of the expiry map, so check for
+    // the map first
+    if let Ok(expiry) = options.deserialize::<Expiry>(bytes) {
+        if options.serialized_size(&expiry).ok() == Some(bytes.len() as u64) {
+            return Found::Expiry(expiry);
+        }
+    }
+
+    match options.deserialize::<(String, Value)>(bytes) {
+        Ok(pair) => match options.serialized_size(&pair) {
+            Ok(len) => Found::Pair(pair.0, pair.1, len as usize),
+            Err(_) => Found::Nothing,
+        },
+        Err(_) => Found::Nothing,
+    }
+}
+
+/// Write to a temporary file first so a failed write never leaves a
+/// truncated storage file behind. The file is on disk when this returns, so
+/// the journal can be removed afterwards. The file is encrypted if a `key` is
+/// given. Returns the stamp of the written file.
+pub fn write_file(
+    path: &Path,
+    storage: &Storage,
+    compression: Compression,
+    key: Option<&EncryptionKey>,
+) -> Result<FileStamp> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(TEMP_SUFFIX);
+
+    let body = serialize(&(&storage.data, &storage.expiry)).context(StorageError::Serialize)?;
+    let body = compression.compress(body)?;
+
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(MAGIC);
+    bytes.push(VERSION);
+    bytes.push(compression.as_byte());
+
+    let body = match key {
+        Some(key) => {
+            bytes.push(1);
+            key.encrypt(&associated_data(&bytes, path), &body)?
+        }
+        None => {
+            bytes.push(0);
+            body
+        }
+    };
+
+    bytes.extend_from_slice(&checksum(&bytes, &body).to_le_bytes());
+    bytes.extend(body);
+
+    let mut file = File::create(&temp_path)?;
+    file.write_all(&bytes)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&temp_path, path)?;
+    sync_directory(path)?;
+
+    Ok(FileStamp::new(&fs::metadata(path)?, &bytes))
+}
+
+/// Wait until the directory entry of a renamed file is on disk
+#[cfg(unix)]
+fn sync_directory(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => File::open(directory)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+/// Windows can not open a directory as a file to sync it
+#[cfg(not(unix))]
+fn sync_directory(_: &Path) -> std::io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,64 @@
+//! What the storage pool holds, for server admins to look into a running
+//! server
+use crate::{
+    lock::LockMode,
+    storage::{Storage, StoragePool},
+};
+use anyhow::Result;
+
+/// State of an open storage
+#[derive(Debug, Clone, PartialEq)]
+pub struct StorageInfo {
+    pub name: String,
+    pub keys: usize,
+    /// Bytes of all keys and values in the storage file, counted against the
+    /// size limits
+    pub size: usize,
+    /// Estimated bytes the storage takes up in memory
+    pub memory: usize,
+    /// Whether there are changes that are not written yet
+    pub dirty: bool,
+    pub lock_mode: LockMode,
+    /// Unix timestamps in milliseconds, `None` if never read or written
+    pub last_read: Option<u64>,
+    pub last_write: Option<u64>,
+}
+
+impl StorageInfo {
+    fn new(storage: &Storage) -> Self {
+        Self {
+            name: storage.name().to_owned(),
+            keys: storage.data.len(),
+            size: storage.size(),
+            memory: storage.memory_size(),
+            dirty: storage.is_dirty(),
+            lock_mode: storage.lock_mode(),
+            last_read: storage.last_read(),
+            last_write: storage.last_write(),
+        }
+    }
+}
+
+impl StoragePool {
+    /// State of all open storages sorted by name
+    pub fn storage_info(&self) -> Vec<StorageInfo> {
+        let mut infos: Vec<StorageInfo> = self
+            .storages()
+            .iter()
+            .map(|storage| StorageInfo::new(&storage.read().unwrap()))
+            .collect();
+
+        infos.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+        infos
+    }
+
+    /// Sorted names of the storage files on disk that are not open
+    pub fn closed_files_on_disk(&self) -> Result<Vec<String>> {
+        let open = self.get_files();
+        let mut files = self.files_on_disk()?;
+        files.retain(|file| !open.contains(file));
+
+        Ok(files)
+    }
+}
@@ -0,0 +1,198 @@
+//! Write-ahead journal of changes to a storage
+//!
+//! Every change is appended to `<storage>.journal` and synced to disk before
+//! it is applied, so it survives a crash even if the storage is never written
+//! afterwards. Reading a storage replays its journal on top of the file and
+//! writing it removes the journal, as the file then holds all changes.
+//!
+//! A record is its length and CRC-32 checksum followed by a byte telling
+//! whether it is encrypted and the bincode encoded [`Record`]. Encrypted
+//! records are bound to the name of their storage. A record cut off by a
+//! crash ends the journal and is removed before appending again. A damaged
+//! record with more bytes after it is not from a crash, the journal is then
+//! left alone and reading it fails.
+use crate::{
+    encryption::EncryptionKey,
+    storage::{Storage, StorageError},
+    Value,
+};
+use anyhow::{ensure, Context, Result};
+use bincode::{deserialize, serialize};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    convert::TryInto,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+pub const JOURNAL_SUFFIX: &str = ".journal";
+
+/// Length and checksum in front of every record
+const FRAME_SIZE: usize = 8;
+
+/// A change to a storage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Record {
+    /// A key set to a value, expiring at a Unix timestamp in milliseconds
+    Set(String, Value, Option<u64>),
+    Erase(String),
+    /// All keys removed
+    Clear,
+}
+
+/// Append records and wait until they are on disk
+pub fn append(storage_path: &Path, key: Option<&EncryptionKey>, records: &[Record]) -> Result<()> {
+    let mut bytes = Vec::new();
+    let associated_data = associated_data(storage_path);
+
+    for record in records {
+        let record = serialize(record).context(StorageError::Serialize)?;
+        let payload = match key {
+            Some(key) => [vec![1], key.encrypt(&associated_data, &record)?].concat(),
+            None => [vec![0], record].concat(),
+        };
+
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        bytes.extend(payload);
+    }
+
+    let path = journal_path(storage_path);
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    let len = file.metadata()?.len();
+
+    if let Err(err) = file.write_all(&bytes).and_then(|_| file.sync_data()) {
+        // never leave half a record in front of the next one
+        let _ = file.set_len(len);
+        return Err(err.into());
+    }
+
+    Ok(())
+}
+
+/// Records of the journal of a storage in order, none if it has no journal
+pub fn read(storage_path: &Path, key: Option<&EncryptionKey>) -> Result<Vec<Record>> {
+    let bytes = match fs::read(journal_path(storage_path)) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut records = Vec::new();
+    let associated_data = associated_data(storage_path);
+
+    for payload in payloads(&bytes)?.0 {
+        let record = match payload.split_first() {
+            Some((1, encrypted)) => key
+                .context(StorageError::KeyRequired)?
+                .decrypt(&associated_data, encrypted)?,
+            Some((_, record)) => record.to_vec(),
+            None => continue,
+        };
+
+        records.push(deserialize(&record).context(StorageError::Deserialize)?);
+    }
+
+    Ok(records)
+}
+
+/// Cut off a record a crash left incomplete so new records can follow
+pub fn repair(storage_path: &Path) -> Result<()> {
+    let path = journal_path(storage_path);
+
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let len = payloads(&bytes)
+        .with_context(|| format!("Journal {} is damaged", path.display()))?
+        .1;
+
+    if len < bytes.len() {
+        warn!(
+            "Removing {} bytes of an incomplete record from {}",
+            bytes.len() - len,
+            path.display()
+        );
+        OpenOptions::new()
+            .write(true)
+            .open(&path)?
+            .set_len(len as u64)?;
+    }
+
+    Ok(())
+}
+
+/// Remove the journal of a storage once its file holds all changes
+pub fn remove(storage_path: &Path) -> Result<()> {
+    match fs::remove_file(journal_path(storage_path)) {
+        Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Associated data of encrypted records, so records can not be moved to the
+/// journal of another storage
+fn associated_data(storage_path: &Path) -> Vec<u8> {
+    let name = storage_path.file_name().unwrap_or_default();
+
+    [&[1], name.to_string_lossy().as_bytes()].concat()
+}
+
+fn journal_path(storage_path: &Path) -> PathBuf {
+    let mut path = storage_path.as_os_str().to_owned();
+    path.push(JOURNAL_SUFFIX);
+    path.into()
+}
+
+/// Payloads of all complete records and the bytes they take up. Only the
+/// last record may be damaged, as only a crash while appending damages it.
+fn payloads(bytes: &[u8]) -> Result<(Vec<&[u8]>, usize)> {
+    let mut payloads = Vec::new();
+    let mut position = 0;
+
+    while let Some(frame) = bytes.get(position..position + FRAME_SIZE) {
+        let len = u32::from_le_bytes(frame[..4].try_into().unwrap()) as usize;
+        let checksum = u32::from_le_bytes(frame[4..].try_into().unwrap());
+        let end = position + FRAME_SIZE + len;
+
+        match bytes.get(position + FRAME_SIZE..end) {
+            Some(payload) if crc32fast::hash(payload) == checksum => payloads.push(payload),
+            Some(_) => {
+                ensure!(end == bytes.len(), StorageError::Deserialize);
+                break;
+            }
+            None => break,
+        }
+
+        position = end;
+    }
+
+    Ok((payloads, position))
+}
+
+impl Storage {
+    /// Apply journaled changes in order
+    pub(crate) fn replay(&mut self, records: Vec<Record>) {
+        for record in records {
+            match record {
+                Record::Set(key, value, expires) => {
+                    self.set_expiry(&key, expires);
+                    self.insert(key, value);
+                }
+                Record::Erase(key) => {
+                    self.remove(&key);
+                    self.expiry.remove(&key);
+                }
+                Record::Clear => {
+                    self.set_data(Default::default());
+                    self.expiry.clear();
+                }
+            }
+        }
+    }
+}
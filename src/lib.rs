@@ -3,27 +3,88 @@
 //! [`callExtension`][callExtension]. We use
 //! this to our advantage so we do not have to destringify the data send to the
 //! extension. Also this is done to be compatible with [`FileXT`][FileXT] so you
-//! can use this extension as a drop-in replacement for [`FileXT`][FileXT]
+//! can use this extension in place of [`FileXT`][FileXT]. Errors are reported
+//! in this extension's format, not with FileXT's error strings.
 //!
 //! This changes the syntax a bit in usage:
 //! ```sqf
 //! "arma_storage" callExtension [data, [function, arg1, arg2, ...]];
 //! ```
 //!
+//! The standard syntaxes work the same way. The function name is passed on its
+//! own and the arguments are separated by `|` when using a single string:
+//! ```sqf
+//! "arma_storage" callExtension [function, [arg1, arg2, ...]];
+//! "arma_storage" callExtension "function|arg1|arg2|...";
+//! ```
+//! With a single string Arma does not stringify the arguments, so string
+//! values have to be quoted: `"set|spam|name|""John"""`. A `|` inside an
+//! argument is written twice: `"set|spam|motto|""Fight||Flight"""`.
+//!
+//! The examples below use the alternative syntax.
+//!
+//! Storages are files in the `arma_storage` directory inside the working
+//! directory of the server or in the directory set by the `ARMA_STORAGE_PATH`
+//! environment variable. Only storages belong in that directory, as `getFiles`
+//! lists and `deleteFile` deletes any file in it. Storage names must be plain
+//! file names and can not end in `.tmp`, `.lock`, `.damaged` or `.journal`,
+//! which are kept next to the storages.
+//!
+//! Both syntaxes take the same functions and arguments. Passing more arguments
+//! than a function takes is an error.
+//!
 //! ## Commands
+//! ### Help
+//!
+//! List all functions or get one, each as `[name, aliases, arguments,
+//! description]`. Every argument is `[name, type, optional]` with `type` being
+//! the SQF type like `"STRING"` or `"ANYTHING"`.
+//!
+//! | | |
+//! | --- | --- |
+//! | **Syntax** | `"arma_storage" callExtension ["", ["help", function]]` |
+//! | **Parameters** | **function**: *String* - function name (optional) |
+//! | **Return Value** | *Array* - all functions or the one passed |
+//!
+//! #### Example
+//! ```sqf
+//! "arma_storage" callExtension ["", ["help", "get"]];
+//! // ["[""get"", [], [[""name"", ""STRING"", false], [""key"", ""STRING"", false]], ""Value of a key""]", 0, 0]
+//! ```
+//!
 //! ### Get Error Codes
+//!
+//! List all [error codes](#error-codes) or get the name of one.
+//!
+//! | | |
+//! | --- | --- |
+//! | **Syntax** | `"arma_storage" callExtension ["", ["errorCodes"]]` |
+//! | | `"arma_storage" callExtension ["", ["errorName", code]]` |
+//! | **Return Value** | *Array* - `[[code, description, name], ...]` or *String* - name of the code |
+//!
+//! #### Example
 //! ```sqf
-//! "arma_storage" callExtension ["", ["errorCodes"]];
+//! "arma_storage" callExtension ["", ["errorName", 22]];
+//! // ["notFound", 0, 0]
 //! ```
 //!
 //! ### Open Storage
 //!
 //! Open a storage. If the storage is already open an error is returned.
 //!
+//! The storage file is locked so other server processes sharing the storage
+//! directory can not open it at the same time:
+//! * `"exclusive"` - no other process can open the storage (default)
+//! * `"shared"` - other processes can open the storage shared, but nobody can write it
+//! * `"unlocked"` - no lock is taken
+//!
+//! The default mode can be changed with the `ARMA_STORAGE_LOCK` environment variable.
+//!
 //! | | |
 //! | --- | --- |
-//! | **Syntax** | `"arma_storage" callExtension ["", ["open", storage]]` |
+//! | **Syntax** | `"arma_storage" callExtension ["", ["open", storage, mode]]` |
 //! | **Parameters** | **storage**: *String* - storage name |
+//! | | **mode** (optional): *String* - lock mode |
 //! | **Return Value** | *nothing* |
 //!
 //! #### Example
@@ -68,6 +129,11 @@
 //! Write a storage file. This overrides the file.
 //! If the storage is not open an error is returned.
 //!
+//! If another program changed the file since the storage was read or written
+//! an error is returned and the file is left alone. Read the storage again to
+//! pick up the changes, or set the `ARMA_STORAGE_CONFLICT` environment variable
+//! to `warn` to overwrite them with a warning in the log.
+//!
 //! | | |
 //! | --- | --- |
 //! | **Syntax** | `"arma_storage" callExtension ["", ["write", storage]]` |
@@ -79,31 +145,472 @@
 //! "arma_storage" callExtension ["", ["write", "spam"]];
 //! ```
 //!
+//! ### Changes on Disk
+//!
+//! Set the `ARMA_STORAGE_WATCH` environment variable to a number of seconds to
+//! check open storages for changes made by other programs that often, e.g. a
+//! second server writing a storage both have opened `"unlocked"`. Changed
+//! storages are read again, replacing any changes not yet written, and raise
+//! the [`ExtensionCallback`][ExtensionCallback] event handler:
+//!
+//! ```sqf
+//! addMissionEventHandler ["ExtensionCallback", {
+//!     params ["_name", "_function", "_data"];
+//!     if (_name == "arma_storage" && _function == "storageChanged") then {
+//!         systemChat format ["Storage %1 was reloaded", _data];
+//!     };
+//! }];
+//! ```
+//!
+//! ### Get Value
+//!
+//! Get the value of a key. If the key does not exist an error is returned.
+//!
+//! | | |
+//! | --- | --- |
+//! | **Syntax** | `"arma_storage" callExtension ["", ["get", storage, key]]` |
+//! | **Parameters** | **storage**: *String* - storage name |
+//! | | **key**: *String* - key name |
+//! | **Return Value** | *Anything* - the stored value |
+//!
+//! ### Set Value
+//!
+//! Set the value of a key. An existing value is replaced.
+//! With a **ttl** the key expires after that many seconds, otherwise it never
+//! expires. Expired keys behave as if they were erased.
+//!
+//! | | |
+//! | --- | --- |
+//! | **Syntax** | `"arma_storage" callExtension ["", ["set", storage, key, value, ttl]]` |
+//! | **Parameters** | **storage**: *String* - storage name |
+//! | | **key**: *String* - key name |
+//! | | **value**: *Anything* - value to store |
+//! | | **ttl** (optional): *Number* - seconds until the key expires |
+//! | **Return Value** | *nothing* |
+//!
+//! ### Time To Live
+//!
+//! Get the remaining lifetime of a key. If the key does not exist an error is returned.
+//!
+//! | | |
+//! | --- | --- |
+//! | **Syntax** | `"arma_storage" callExtension ["", ["ttl", storage, key]]` |
+//! | **Parameters** | **storage**: *String* - storage name |
+//! | | **key**: *String* - key name |
+//! | **Return Value** | *Number* - seconds until the key expires or `-1` if it never expires |
+//!
+//! #### Example
+//! ```sqf
+//! "arma_storage" callExtension ["", ["set", "spam", "cooldown_" + getPlayerUID player, true, 300]];
+//! ```
+//!
+//! ### Erase Key
+//!
+//! Remove a key. If the key does not exist an error is returned.
+//!
+//! | | |
+//! | --- | --- |
+//! | **Syntax** | `"arma_storage" callExtension ["", ["eraseKey", storage, key]]` |
+//! | **Parameters** | **storage**: *String* - storage name |
+//! | | **key**: *String* - key name |
+//! | **Return Value** | *nothing* |
+//!
+//! ### Get Files
+//!
+//! List the names of all storage files in the storage directory, like
+//! [`FileXT`][FileXT] does. Use `storages` to list only the open storages,
+//! which is what `getFiles` means with the standard syntax.
+//!
+//! | | |
+//! | --- | --- |
+//! | **Syntax** | `"arma_storage" callExtension ["", ["getFiles"]]` |
+//! | **Return Value** | *Array* - storage names |
+//!
+//! ### Delete File
+//!
+//! Delete a storage file. An open storage keeps its data in memory and can be
+//! written again. A storage another server has locked is not deleted.
+//!
+//! | | |
+//! | --- | --- |
+//! | **Syntax** | `"arma_storage" callExtension ["", ["deleteFile", storage]]` |
+//! | **Parameters** | **storage**: *String* - storage name |
+//! | **Return Value** | *nothing* |
+//!
+//! ### Export and Import
+//!
+//! Export all keys and values of a storage as text to the `export` directory
+//! inside the storage directory, or replace the data of a storage with an
+//! exported file. The file holds an array of `[key, value]` pairs that can be
+//! read with `parseSimpleArray` unless the file name ends in `.sqf`, which adds
+//! a comment on top and needs `call compile`. Arrays holding sides also need
+//! `call compile`. Expiry times are not exported.
+//!
+//! | | |
+//! | --- | --- |
+//! | **Syntax** | `"arma_storage" callExtension ["", ["export", storage, file]]` |
+//! | | `"arma_storage" callExtension ["", ["import", storage, file]]` |
+//! | **Parameters** | **storage**: *String* - storage name |
+//! | | **file**: *String* - file name, optional for `export` and defaults to `storage.sqf` |
+//! | **Return Value** | *String* - the file name for `export`, *nothing* for `import` |
+//!
+//! #### Example
+//! ```sqf
+//! "arma_storage" callExtension ["", ["export", "spam", "spam.txt"]];
+//! "arma_storage" callExtension ["", ["import", "eggs", "spam.txt"]];
+//! ```
+//!
+//! ### List Keys
+//!
+//! List the keys of a storage in sorted order. `keysWithPrefix` only returns
+//! keys starting with a prefix, `keysMatching` keys matching a glob pattern
+//! where `*` matches any number of characters and `?` exactly one.
+//!
+//! The optional **offset** and **limit** page through large storages so the
+//! result fits into the response buffer.
+//!
+//! | | |
+//! | --- | --- |
+//! | **Syntax** | `"arma_storage" callExtension ["", ["keys", storage, offset, limit]]` |
+//! | | `"arma_storage" callExtension ["", ["keysWithPrefix", storage, prefix, offset, limit]]` |
+//! | | `"arma_storage" callExtension ["", ["keysMatching", storage, pattern, offset, limit]]` |
+//! | **Return Value** | *Array* - key names |
+//!
+//! #### Example
+//! ```sqf
+//! "arma_storage" callExtension ["", ["keysMatching", "spam", "player_*_money", 0, 50]];
+//! ```
+//!
+//! ### Get and Set Many
+//!
+//! Get or set multiple keys of a storage with a single call. The result holds
+//! one status per key in the order they were passed, `[0, value]` on success,
+//! `[0]` for keys set, or the [error](#error-codes) for the key.
+//!
+//! | | |
+//! | --- | --- |
+//! | **Syntax** | `"arma_storage" callExtension ["", ["getMany", storage, [key1, key2, ...]]]` |
+//! | | `"arma_storage" callExtension ["", ["setMany", storage, [[key1, value1], ...]]]` |
+//! | **Return Value** | *Array* - one `[0, value]`, `[0]` or error per key |
+//!
+//! `getMany` returns the value for every existing key. `setMany` sets either
+//! all keys or none. If any entry is malformed it fails with code `12` and
+//! identifier `invalidEntries`, the context holding the status of every entry.
+//!
+//! #### Example
+//! ```sqf
+//! "arma_storage" callExtension ["", ["getMany", "spam", ["money", "rank"]]];
+//! // [[[0, 100], [22, "keyNotFound", "Storage has no key rank", "rank"]], 0, 0]
+//! ```
+//!
+//! ### Transactions
+//!
+//! Stage multiple changes to one storage and apply them all at once.
+//! On commit the changes are applied and written to the storage file. Other
+//! changes to the storage that are not written yet stay in memory only. If any
+//! change fails, e.g. erasing a missing key, nothing is applied and the file is
+//! left unchanged. A transaction is finished after `commit` or `rollback`, even
+//! if the commit failed. Closing a storage discards its transactions.
+//! Transaction ids start over after 16777216 so they stay exact in SQF.
+//!
+//! | Function | Arguments | Return Value |
+//! | -------- | --------- | ------------ |
+//! | `begin` | storage | *Number* - transaction id |
+//! | `txSet` | transaction, key, value | *nothing* |
+//! | `txErase` | transaction, key | *nothing* |
+//! | `commit` | transaction | *nothing* |
+//! | `rollback` | transaction | *nothing* |
+//!
+//! #### Example
+//! ```sqf
+//! private _tx = parseNumber ("arma_storage" callExtension ["", ["begin", "bank"]] select 0);
+//! "arma_storage" callExtension ["", ["txSet", _tx, "alice", 50]];
+//! "arma_storage" callExtension ["", ["txSet", _tx, "bob", 150]];
+//! "arma_storage" callExtension ["", ["commit", _tx]];
+//! ```
+//!
+//! ### Atomic Operations
+//!
+//! These modify a value inside the extension so concurrent scripts do not
+//! race between a `get` and a `set`.
+//!
+//! | Function | Arguments | Type | Return Value |
+//! | -------- | --------- | ---- | ------------ |
+//! | `increment` | storage, key, *amount* (default `1`) | *Number* | the new number |
+//! | `decrement` | storage, key, *amount* (default `1`) | *Number* | the new number |
+//! | `push` | storage, key, value | *Array* | the new length |
+//! | `pushUnique` | storage, key, value | *Array* | `true` if the value was added |
+//! | `removeAt` | storage, key, index | *Array* | the removed element |
+//! | `removeValue` | storage, key, value | *Array* | the number of removed elements |
+//! | `toggle` | storage, key | *Boolean* | the new state |
+//! | `compareAndSet` | storage, key, expected, new | *Anything* | `true` if the value was replaced |
+//!
+//! `increment`, `decrement`, `push` and `pushUnique` treat a missing key as `0`
+//! or `[]`. All other operations return an error for a missing key, except
+//! `compareAndSet` which returns `false`.
+//!
+//! `compareAndSet` compares values structurally. Numbers are equal if they
+//! print the same in SQF, as Arma only passes six significant digits.
+//! `pushUnique` and `removeValue` compare values exactly.
+//!
+//! #### Example
+//! ```sqf
+//! "arma_storage" callExtension ["", ["increment", "spam", "kills", 2]];
+//! "arma_storage" callExtension ["", ["pushUnique", "spam", "players", getPlayerUID player]];
+//! ```
+//!
+//! ### Compression
+//!
+//! Storage files can be compressed with `"zstd"` for smaller files or `"lz4"`
+//! for faster reads and writes. Set the `ARMA_STORAGE_COMPRESSION` environment
+//! variable to compress all storages, or set the compression of a single
+//! storage for its next writes. Otherwise a storage keeps the compression of
+//! its file. Compressed files are read like any other.
+//!
+//! | | |
+//! | --- | --- |
+//! | **Syntax** | `"arma_storage" callExtension ["", ["compression", storage, compression]]` |
+//! | **Parameters** | **storage**: *String* - storage name |
+//! | | **compression**: *String* - `"none"`, `"zstd"` or `"lz4"` |
+//! | **Return Value** | *nothing* |
+//!
+//! ### Encryption
+//!
+//! Storage files are encrypted with ChaCha20-Poly1305 when a key is set,
+//! either as 64 hexadecimal digits in the `ARMA_STORAGE_KEY` environment
+//! variable or in a file named by `ARMA_STORAGE_KEY_FILE`. Unencrypted files
+//! are still read with a warning in the log and get encrypted on their next
+//! write. Reading an encrypted file without the key or with a wrong one fails,
+//! as does reading a file changed by anything but the extension or copied
+//! over the file of another storage.
+//!
+//! ### Journal
+//!
+//! Writing a storage rewrites its whole file, which is too slow to do after
+//! every change. With `ARMA_STORAGE_JOURNAL` set to `true` every change is
+//! appended to `<storage>.journal` and synced to disk before the function
+//! returns, so a crash between two writes loses nothing. Reading a storage
+//! replays its journal on top of the file, writing it removes the journal and
+//! closing it removes the journal with the changes that were not written.
+//!
+//! ### Audit Log
+//!
+//! With `ARMA_STORAGE_AUDIT` set to `true` every change is logged to
+//! `audit/changes.log` in the storage directory with its time, old and new
+//! value. Wrap a call in `as` to log who made the change, e.g. the name and
+//! UID of a player. The log is never rotated, move it away while the server
+//! is stopped to start a new one.
+//!
+//! | | |
+//! | --- | --- |
+//! | **Syntax** | `"arma_storage" callExtension ["", ["as", caller, function, arguments...]]` |
+//! | **Parameters** | **caller**: *String* - tag logged with all changes of the call |
+//! | | **function**: *String* - function to call with the following arguments |
+//! | **Return Value** | the result of the function |
+//!
+//! `history` returns the last changes of a key, newest first. An entry is
+//! `[time, storage, key, old, new, caller]` where `old` and `new` are `[]` if
+//! the key was missing and `[value]` otherwise.
+//!
+//! | | |
+//! | --- | --- |
+//! | **Syntax** | `"arma_storage" callExtension ["", ["history", storage, key, n]]` |
+//! | **Parameters** | **storage**: *String* - storage name |
+//! | | **key**: *String* - key name |
+//! | | **n**: *Number* - (optional) number of changes, all if not given |
+//! | **Return Value** | *Array* - changes |
+//!
+//! `exportAudit` writes the audit log as a single array to the export
+//! directory and returns the number of entries.
+//!
+//! | | |
+//! | --- | --- |
+//! | **Syntax** | `"arma_storage" callExtension ["", ["exportAudit", file, storage]]` |
+//! | **Parameters** | **file**: *String* - file name in the export directory |
+//! | | **storage**: *String* - (optional) only changes of this storage |
+//! | **Return Value** | *Number* - exported entries |
+//!
+//! #### Example
+//! ```sqf
+//! "arma_storage" callExtension ["", ["as", format ["%1 (%2)", name player, getPlayerUID player], "decrement", "bank", "money", 100]];
+//! "arma_storage" callExtension ["", ["history", "bank", "money", 10]];
+//! ```
+//!
+//! ### Verify and Salvage
+//!
+//! Storage files carry a checksum, so damage is reported as such instead of
+//! as garbled data. `verify` checks the file of a storage without reading it.
+//! `salvage` reads the keys of a damaged file that are still intact into an
+//! open storage and keeps a copy of the damaged file as `<storage>.damaged`.
+//! Write the storage afterwards to replace the damaged file.
+//!
+//! | | |
+//! | --- | --- |
+//! | **Syntax** | `"arma_storage" callExtension ["", ["verify", storage]]` |
+//! | **Parameters** | **storage**: *String* - storage name |
+//! | **Return Value** | *Array* - `[true, ""]` if the file is intact, otherwise `[false, reason]` |
+//!
+//! | | |
+//! | --- | --- |
+//! | **Syntax** | `"arma_storage" callExtension ["", ["salvage", storage]]` |
+//! | **Parameters** | **storage**: *String* - storage name |
+//! | **Return Value** | *Number* - recovered keys |
+//!
+//! ### Limits and Stats
+//!
+//! Environment variables limit how much data scripts can store. They are
+//! unlimited if not set. Sizes are the bytes keys and values take up in the
+//! storage file. A change exceeding a limit fails with error code `21` and
+//! leaves the storage unchanged, changes that shrink a storage always work.
+//!
+//! | Variable | Limit |
+//! | -------- | ----- |
+//! | `ARMA_STORAGE_MAX_KEYS` | keys per storage |
+//! | `ARMA_STORAGE_MAX_VALUE_SIZE` | bytes per value |
+//! | `ARMA_STORAGE_MAX_STORAGE_SIZE` | bytes per storage |
+//! | `ARMA_STORAGE_MAX_TOTAL_SIZE` | bytes of all open storages |
+//! | `ARMA_STORAGE_MAX_OPEN` | open storages |
+//!
+//! `stats` returns the usage of a storage and all storages next to the limits,
+//! which are `-1` if unlimited.
+//!
+//! | | |
+//! | --- | --- |
+//! | **Syntax** | `"arma_storage" callExtension ["", ["stats", storage]]` |
+//! | **Parameters** | **storage**: *String* - storage name |
+//! | **Return Value** | *Array* - `[[name, number], ...]` for `keys`, `maxKeys`, `size`, `maxSize`, `maxValueSize`, `totalSize`, `maxTotalSize`, `openStorages` and `maxOpenStorages` |
+//!
+//! #### Example
+//! ```sqf
+//! private _stats = createHashMapFromArray parseSimpleArray ("arma_storage" callExtension ["", ["stats", "spam"]] select 0);
+//! _stats get "keys";
+//! ```
+//!
+//! ### Server Info
+//!
+//! Look into a running server without restarting it. `info` returns the
+//! version, the configuration set by environment variables, the storage
+//! directory and the seconds since the extension was loaded. `openStorages`
+//! returns the state of every open storage. `size` is the bytes of its keys
+//! and values in the file, `memory` an estimate of the bytes it takes up in
+//! memory. `dirty` means it has changes that are not written yet, times are
+//! UTC or `""` if never. `diskStorages` lists
+//! the storage files that are not open.
+//!
+//! | | |
+//! | --- | --- |
+//! | **Syntax** | `"arma_storage" callExtension ["", ["info"]]` |
+//! | | `"arma_storage" callExtension ["", ["openStorages"]]` |
+//! | | `"arma_storage" callExtension ["", ["diskStorages"]]` |
+//! | **Return Value** | `info`: *Array* - `[[name, value], ...]` for `version`, `path`, `uptime`, `lockMode`, `conflictMode`, `compression`, `journal`, `audit`, `encrypted`, `maxKeys`, `maxSize`, `maxValueSize`, `maxTotalSize` and `maxOpenStorages` |
+//! | | `openStorages`: *Array* - `[[[name, value], ...], ...]` for `name`, `keys`, `size`, `memory`, `dirty`, `lockMode`, `lastRead` and `lastWrite` |
+//! | | `diskStorages`: *Array* - storage names |
+//!
+//! #### Example
+//! ```sqf
+//! {
+//!     private _storage = createHashMapFromArray _x;
+//!     if (_storage get "dirty") then {
+//!         diag_log format ["%1 has unwritten changes", _storage get "name"];
+//!     };
+//! } forEach parseSimpleArray ("arma_storage" callExtension ["", ["openStorages"]] select 0);
+//! ```
+//!
+//!
+//! ## SQF Function Library
+//!
+//! `sqf/arma_storage` holds functions wrapping every command, e.g.
+//! `AS_fnc_get` or `AS_fnc_set`. Copy the folder into a mission and include
+//! `arma_storage\CfgFunctions.hpp` in its `description.ext`. The functions
+//! take the arguments of their command as an array and return the parsed
+//! result, or `nil` with the [error](#error-codes) in `AS_lastError`.
+//! `AS_fnc_addCallback` runs code on callbacks like `storageChanged`.
+//!
+//! ```sqf
+//! ["spam"] call AS_fnc_open;
+//! ["spam", "money", 100] call AS_fnc_set;
+//! private _money = ["spam", "money"] call AS_fnc_get;
+//! ```
+//!
+//! The library is generated from the commands of the extension and written
+//! again with `arma-storage-cli sqf <directory>`.
+//!
+//! Results larger than the buffer Arma passes are returned in chunks with
+//! code `3` and `[id, count, code]`, `code` being the code of the result.
+//! `["chunk", id, index]` returns each chunk as a string, joined they are the
+//! result. Chunks not fetched within a minute are dropped. `AS_fnc_call` does
+//! this for all functions of the library.
+//!
+//! ## Command Line Tool
+//!
+//! `arma-storage-cli` reads and edits storage files directly, e.g. to fix a
+//! broken save while the server is down. Run it without arguments to list its
+//! commands. Do not edit storages the server has open.
+//!
+//! ```sh
+//! arma-storage-cli --path storages get players money
+//! arma-storage-cli --path storages set players money 100
+//! arma-storage-cli --path storages verify
+//! arma-storage-cli --path storages salvage players
+//! arma-storage-cli sqf missions/my_mission/arma_storage
+//! ```
 //!
 //! ## Error Codes
 //!
-//! With some errors the result contains more information about what went wrong.
+//! A failed call returns the error code and as result an array
+//! `[code, identifier, message, context]`. The `identifier` is a stable name
+//! to branch on, more specific than the code, e.g. `"keyNotFound"` or
+//! `"storageNotOpen"`. `message` describes the error with its cause and
+//! `context` is what the error is about, e.g. the missing key or argument, or
+//! `""`.
 //!
-//! | Code | Meaning | Result Value |
-//! | :--: | :------ | ------------ |
-//! | 0 | no error | nothing |
-//! | 1 | A parameter is not valid UTF-8 | nothing |
-//! | 2 | The function passed is unknown | The function name |
-//! | 10 | Missing a required argument | The name of the argument |
-//! | 11 | Argument is empty| The name of the argument |
-//! | 20 | A error in the storage occured | The exact error with cause |
+//! The codes with their names and the identifiers they are returned with
+//! are listed at [`ErrorCodes`].
+//!
+//! #### Example
+//! ```sqf
+//! private _result = "arma_storage" callExtension ["", ["get", "spam", "rank"]];
+//! // ["[22,""keyNotFound"",""Storage has no key rank"",""rank""]", 22, 0]
+//! (parseSimpleArray (_result select 0)) params ["_code", "_identifier", "_message", "_context"];
+//! ```
 //!
 //! [FileXT]: https://github.com/Vindicta-Team/FileXT
+//! [ExtensionCallback]: https://community.bistudio.com/wiki/Arma_3:_Mission_Event_Handlers#ExtensionCallback
 /// [callExtension]: https://community.bistudio.com/wiki/callExtension
+mod atomic;
+mod audit;
+mod callback;
+mod chunk;
+mod encryption;
 mod error;
+mod export;
 mod extension;
+mod filext;
+mod format;
+mod info;
+mod journal;
+mod limits;
+mod lock;
 mod memory;
+mod registry;
+mod sqf;
 mod storage;
+mod transaction;
 mod value;
+mod watch;
 
-pub use error::ErrorCodes;
-pub use storage::Storage;
+pub use audit::AuditEntry;
+pub use encryption::EncryptionKey;
+pub use error::{ErrorCodes, ExtensionError};
+pub use format::{Compression, Verification};
+pub use info::StorageInfo;
+pub use limits::{Limits, Stats};
+pub use lock::LockMode;
+pub use sqf::sqf_library;
+pub use storage::{KeyFilter, Storage, StorageError, StoragePool};
+pub use transaction::Operation;
 pub use value::Value;
+pub use watch::ConflictMode;
 
 use log::{error, info};
 use memory::write_str_to_ptr;
@@ -114,10 +621,15 @@ use std::{
 };
 
 /// This function gets called when loading an extension
+///
+/// # Safety
+/// `response_ptr` must point to a writable buffer of at least `response_size` bytes.
 #[allow(non_snake_case)]
 #[no_mangle]
 pub unsafe extern "system" fn RVExtensionVersion(response_ptr: *mut c_char, response_size: c_int) {
-    env_logger::init();
+    // Arma only loads the extension once, but tests call this repeatedly
+    let _ = env_logger::try_init();
+    extension::mark_loaded();
 
     let version = env!("CARGO_PKG_VERSION");
 
@@ -130,8 +642,20 @@ pub unsafe extern "system" fn RVExtensionVersion(response_ptr: *mut c_char, resp
     );
 }
 
+/// This function gets called when loading an extension with a function Arma
+/// calls to raise the `ExtensionCallback` mission event handler
+#[allow(non_snake_case)]
+#[no_mangle]
+pub extern "system" fn RVExtensionRegisterCallback(callback: callback::Callback) {
+    callback::register(callback);
+}
+
 /// This function gets called when using the standard syntax of [`callExtension`][callExtension]
 ///
+/// # Safety
+/// `response_ptr` must point to a writable buffer of at least `response_size` bytes and
+/// `function_name_ptr` must point to a null terminated string.
+///
 /// [callExtension]: https://community.bistudio.com/wiki/callExtension
 #[allow(non_snake_case)]
 #[no_mangle]
@@ -153,13 +677,16 @@ pub unsafe extern "system" fn RVExtension(
 
     let (error_code, result) = extension::ext(function);
 
-    write_str_to_ptr(&result.as_sqf(), response_ptr, response_size as usize);
-
-    error_code as c_int
+    respond(error_code, result, response_ptr, response_size)
 }
 
 /// This function gets called when using the alternative syntax of [`callExtension`][callExtension]
 ///
+/// # Safety
+/// `response_ptr` must point to a writable buffer of at least `response_size` bytes,
+/// `function_name_ptr` must point to a null terminated string and `argv` must point to
+/// `argc` null terminated strings.
+///
 /// [callExtension]: https://community.bistudio.com/wiki/callExtension
 #[allow(non_snake_case)]
 #[no_mangle]
@@ -171,7 +698,12 @@ pub unsafe extern "system" fn RVExtensionArgs(
     argc: c_int,
 ) -> c_int {
     let argc = argc as usize;
-    let raw_args = slice::from_raw_parts(argv, argc);
+    // `argv` may be null without arguments
+    let raw_args = if argc == 0 || argv.is_null() {
+        &[]
+    } else {
+        slice::from_raw_parts(argv, argc)
+    };
     let args: Vec<&str> = match raw_args
         .iter()
         .map(|&c_str| CStr::from_ptr(c_str).to_str())
@@ -197,7 +729,19 @@ pub unsafe extern "system" fn RVExtensionArgs(
 
     let (error_code, result) = extension::ext_args(function, args);
 
-    write_str_to_ptr(&result.as_sqf(), response_ptr, response_size as usize);
+    respond(error_code, result, response_ptr, response_size)
+}
+
+/// Write a result into the response buffer, in chunks if it does not fit
+unsafe fn respond(
+    error_code: ErrorCodes,
+    result: Value,
+    response_ptr: *mut c_char,
+    response_size: c_int,
+) -> c_int {
+    let (error_code, result) = chunk::fit(error_code, result.as_sqf(), response_size as usize);
+
+    write_str_to_ptr(&result, response_ptr, response_size as usize);
 
-    error_code as c_int
+    error_code.into()
 }
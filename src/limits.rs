@@ -0,0 +1,93 @@
+//! Limits against scripts filling up the memory and disk of the server
+//!
+//! Sizes are the bytes keys and values take up in the storage file. Limits are
+//! only checked when data is added, so storages read from disk or changes
+//! shrinking a storage are never rejected.
+use crate::{storage::StorageError, Value};
+use anyhow::{ensure, Result};
+
+/// `None` means unlimited
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    pub max_keys: Option<usize>,
+    pub max_value_size: Option<usize>,
+    pub max_storage_size: Option<usize>,
+    /// Size of all open storages together
+    pub max_total_size: Option<usize>,
+    pub max_open_storages: Option<usize>,
+}
+
+/// Usage of a storage and the whole pool
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub keys: usize,
+    pub size: usize,
+    pub total_size: usize,
+    pub open_storages: usize,
+}
+
+/// Size of a storage before and after a change
+pub(crate) struct Change {
+    pub keys: (usize, usize),
+    pub size: (usize, usize),
+    pub total_size: usize,
+}
+
+impl Limits {
+    /// Make sure a change of a storage adding `values` stays within the limits
+    pub(crate) fn check<'a>(
+        &self,
+        change: Change,
+        values: impl IntoIterator<Item = &'a Value>,
+    ) -> Result<()> {
+        if let Some(max) = self.max_value_size {
+            for value in values {
+                ensure!(
+                    value.size() <= max,
+                    StorageError::LimitExceeded {
+                        limit: "bytes per value",
+                        max
+                    }
+                );
+            }
+        }
+
+        let (keys_before, keys) = change.keys;
+        if let Some(max) = self.max_keys {
+            ensure!(
+                keys <= keys_before || keys <= max,
+                StorageError::LimitExceeded {
+                    limit: "keys per storage",
+                    max
+                }
+            );
+        }
+
+        let (size_before, size) = change.size;
+        if size <= size_before {
+            return Ok(());
+        }
+
+        if let Some(max) = self.max_storage_size {
+            ensure!(
+                size <= max,
+                StorageError::LimitExceeded {
+                    limit: "bytes per storage",
+                    max
+                }
+            );
+        }
+
+        if let Some(max) = self.max_total_size {
+            ensure!(
+                change.total_size + size - size_before <= max,
+                StorageError::LimitExceeded {
+                    limit: "bytes in all storages",
+                    max
+                }
+            );
+        }
+
+        Ok(())
+    }
+}
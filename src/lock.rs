@@ -0,0 +1,104 @@
+//! Advisory locks so multiple server processes sharing a storage directory do
+//! not overwrite each other's storages
+//!
+//! The storage file itself is replaced on every write, so the lock is held on
+//! a separate `<storage>.lock` file next to it.
+use crate::storage::StorageError;
+use anyhow::Result;
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions, TryLockError},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Appended to the storage file name to get the lock file name
+pub const LOCK_SUFFIX: &str = ".lock";
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LockMode {
+    /// No other process can open the storage
+    #[default]
+    Exclusive,
+    /// Other processes can open the storage shared as well, but nobody can
+    /// write it
+    Shared,
+    /// Do not lock the storage at all
+    Unlocked,
+}
+
+impl FromStr for LockMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exclusive" => Ok(LockMode::Exclusive),
+            "shared" => Ok(LockMode::Shared),
+            "unlocked" => Ok(LockMode::Unlocked),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for LockMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            LockMode::Exclusive => "exclusive",
+            LockMode::Shared => "shared",
+            LockMode::Unlocked => "unlocked",
+        })
+    }
+}
+
+/// Lock a storage file exclusively if it has a lock file, failing if another
+/// process holds it. Without a lock file nobody holds a lock.
+pub fn check(storage_path: &Path, name: &str) -> Result<Option<File>> {
+    if !lock_path(storage_path).exists() {
+        return Ok(None);
+    }
+
+    lock(storage_path, name, LockMode::Exclusive)
+}
+
+/// Lock a storage file. The lock is released when the returned file is dropped.
+pub fn lock(storage_path: &Path, name: &str, mode: LockMode) -> Result<Option<File>> {
+    if mode == LockMode::Unlocked {
+        return Ok(None);
+    }
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(lock_path(storage_path))?;
+
+    let result = match mode {
+        LockMode::Exclusive => file.try_lock(),
+        _ => file.try_lock_shared(),
+    };
+
+    match result {
+        Ok(()) => Ok(Some(file)),
+        Err(TryLockError::WouldBlock) => {
+            Err(StorageError::LockedByOtherProcess(name.to_owned()).into())
+        }
+        Err(TryLockError::Error(err)) => Err(err.into()),
+    }
+}
+
+/// Remove the lock file of a storage. Only call this while holding the lock
+/// returned by [`check`], so nobody else is using it.
+pub fn remove(storage_path: &Path) -> Result<()> {
+    match fs::remove_file(lock_path(storage_path)) {
+        Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+fn lock_path(storage_path: &Path) -> PathBuf {
+    let mut lock_path = storage_path.as_os_str().to_owned();
+    lock_path.push(LOCK_SUFFIX);
+    lock_path.into()
+}
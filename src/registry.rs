@@ -0,0 +1,385 @@
+//! Declarative registry of the commands scripts can call
+//!
+//! A [`Command`] lists its names, the arguments it takes and the handler
+//! running it. Arguments are checked and parsed against that schema before
+//! the handler runs, so handlers only ever see valid arguments. Both syntaxes
+//! of `callExtension` dispatch through the same table, which `["help"]` and
+//! the SQF function library are generated from as well.
+use crate::{
+    error::{ErrorCodes, ExtensionError},
+    extension::{argument_error, unknown_function, Response, COMMANDS},
+    filext, Value,
+};
+use log::error;
+use std::{borrow::Cow, time::Duration};
+
+/// Syntax of `callExtension` a command was called with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Syntax {
+    Standard,
+    /// The alternative syntax, compatible with FileXT
+    FileXt,
+}
+
+/// What an argument is parsed into
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ArgType {
+    /// Non-empty string
+    String,
+    /// Any SQF value
+    Value,
+    /// SQF array
+    Array,
+    Number,
+    /// Non-negative whole number
+    Index,
+    /// Positive number of seconds
+    Seconds,
+    /// Id of a transaction returned by `begin`
+    Transaction,
+}
+
+impl ArgType {
+    /// SQF type the argument is passed as
+    pub fn sqf_type(self) -> &'static str {
+        match self {
+            ArgType::String => "STRING",
+            ArgType::Value => "ANYTHING",
+            ArgType::Array => "ARRAY",
+            ArgType::Number | ArgType::Index | ArgType::Seconds | ArgType::Transaction => "NUMBER",
+        }
+    }
+}
+
+/// Argument of a command, named like in argument errors
+pub(crate) struct Arg {
+    pub name: &'static str,
+    pub kind: ArgType,
+    pub optional: bool,
+}
+
+impl Arg {
+    const fn new(name: &'static str, kind: ArgType) -> Self {
+        Self {
+            name,
+            kind,
+            optional: false,
+        }
+    }
+
+    pub const fn string(name: &'static str) -> Self {
+        Self::new(name, ArgType::String)
+    }
+
+    pub const fn value(name: &'static str) -> Self {
+        Self::new(name, ArgType::Value)
+    }
+
+    pub const fn array(name: &'static str) -> Self {
+        Self::new(name, ArgType::Array)
+    }
+
+    pub const fn number(name: &'static str) -> Self {
+        Self::new(name, ArgType::Number)
+    }
+
+    pub const fn index(name: &'static str) -> Self {
+        Self::new(name, ArgType::Index)
+    }
+
+    pub const fn seconds(name: &'static str) -> Self {
+        Self::new(name, ArgType::Seconds)
+    }
+
+    pub const fn transaction() -> Self {
+        Self::new("transaction", ArgType::Transaction)
+    }
+
+    /// The argument may be left out, only followed by other optional ones
+    pub const fn optional(self) -> Self {
+        Self {
+            optional: true,
+            ..self
+        }
+    }
+}
+
+pub(crate) type Handler = fn(&Args) -> Result<Value, Response>;
+
+/// A command scripts can call
+pub(crate) struct Command {
+    pub name: &'static str,
+    /// Other names the command can be called with
+    pub aliases: &'static [&'static str],
+    pub args: &'static [Arg],
+    /// Whether any number of arguments may follow the declared ones
+    pub variadic: bool,
+    pub handler: Handler,
+    pub description: &'static str,
+}
+
+impl Command {
+    pub const fn new(
+        name: &'static str,
+        args: &'static [Arg],
+        handler: Handler,
+        description: &'static str,
+    ) -> Self {
+        Self {
+            name,
+            aliases: &[],
+            args,
+            variadic: false,
+            handler,
+            description,
+        }
+    }
+
+    pub const fn aliases(self, aliases: &'static [&'static str]) -> Self {
+        Self { aliases, ..self }
+    }
+
+    pub const fn variadic(self) -> Self {
+        Self {
+            variadic: true,
+            ..self
+        }
+    }
+
+    /// Check and parse the stringified `args`, then run the handler
+    pub fn run(&self, syntax: Syntax, args: &[&str]) -> Result<Value, Response> {
+        if !self.variadic && args.len() > self.args.len() {
+            let err = ExtensionError::new(
+                ErrorCodes::InvalidArgument,
+                "tooManyArguments",
+                format!(
+                    "{} takes at most {} arguments but got {}",
+                    self.name,
+                    self.args.len(),
+                    args.len()
+                ),
+                Value::Number(self.args.len() as f32),
+            );
+
+            return Err((err.code, err.to_value()));
+        }
+
+        let parsed = self
+            .args
+            .iter()
+            .enumerate()
+            .map(|(index, arg)| match args.get(index) {
+                None if arg.optional => Ok(None),
+                raw => parse(raw.copied(), arg).map(Some),
+            })
+            .collect::<Result<_, _>>()?;
+
+        (self.handler)(&Args {
+            syntax,
+            raw: args,
+            parsed,
+        })
+    }
+
+    /// Entry of the `help` listing: `[name, aliases, args, description]`,
+    /// each argument being `[name, type, optional]`
+    pub fn to_value(&self) -> Value {
+        let string = |string: &str| Value::String(string.into());
+
+        let mut args: Vec<Value> = self
+            .args
+            .iter()
+            .map(|arg| {
+                Value::Array(vec![
+                    string(arg.name),
+                    string(arg.kind.sqf_type()),
+                    Value::Boolean(arg.optional),
+                ])
+            })
+            .collect();
+
+        if self.variadic {
+            args.push(Value::Array(vec![
+                string("..."),
+                string(ArgType::Value.sqf_type()),
+                Value::Boolean(true),
+            ]));
+        }
+
+        Value::Array(vec![
+            string(self.name),
+            Value::Array(self.aliases.iter().map(|alias| string(alias)).collect()),
+            Value::Array(args),
+            string(self.description),
+        ])
+    }
+}
+
+/// Find a command by its name or an alias
+pub(crate) fn lookup(syntax: Syntax, name: &str) -> Option<&'static Command> {
+    let name = match syntax {
+        Syntax::Standard => name,
+        Syntax::FileXt => filext::command_name(name),
+    };
+
+    COMMANDS
+        .iter()
+        .find(|command| command.name == name || command.aliases.contains(&name))
+}
+
+/// Run the command called `name` with the stringified arguments following it
+pub(crate) fn call(syntax: Syntax, name: &str, args: &[&str]) -> Result<Value, Response> {
+    if name.is_empty() {
+        return Err(argument_error(ErrorCodes::EmptyArgument, "function"));
+    }
+
+    lookup(syntax, name)
+        .ok_or_else(|| unknown_function(name))?
+        .run(syntax, args)
+}
+
+enum Parsed<'a> {
+    String(Cow<'a, str>),
+    Value(Value),
+    Number(f32),
+    Index(usize),
+    Seconds(Duration),
+    Transaction(u32),
+}
+
+fn parse<'a>(raw: Option<&'a str>, arg: &Arg) -> Result<Parsed<'a>, Response> {
+    let raw = raw.ok_or_else(|| argument_error(ErrorCodes::MissingArgument, arg.name))?;
+    let invalid = || argument_error(ErrorCodes::InvalidArgument, arg.name);
+
+    if arg.kind == ArgType::String {
+        let string = unquote(raw);
+
+        if string.is_empty() {
+            return Err(argument_error(ErrorCodes::EmptyArgument, arg.name));
+        }
+
+        return Ok(Parsed::String(string));
+    }
+
+    let value: Value = raw.parse().map_err(|err| {
+        error!("Could not parse argument {}: {}", arg.name, err);
+        invalid()
+    })?;
+
+    let number = match value {
+        Value::Number(number) => number,
+        Value::Array(_) if arg.kind == ArgType::Array => return Ok(Parsed::Value(value)),
+        _ if arg.kind == ArgType::Value => return Ok(Parsed::Value(value)),
+        _ => return Err(invalid()),
+    };
+
+    match arg.kind {
+        ArgType::Number => Ok(Parsed::Number(number)),
+        ArgType::Index if number >= 0. && number.fract() == 0. => {
+            Ok(Parsed::Index(number as usize))
+        }
+        ArgType::Seconds if number > 0. => Duration::try_from_secs_f32(number)
+            .map(Parsed::Seconds)
+            .map_err(|_| invalid()),
+        ArgType::Transaction if number >= 1. && number.fract() == 0. => {
+            Ok(Parsed::Transaction(number as u32))
+        }
+        ArgType::Value => Ok(Parsed::Value(value)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Strip one pair of surrounding quotes from a stringified STRING and turn
+/// its doubled quotes back into single ones, like `Value::from_str` does.
+/// Unquoted arguments of the single string syntax are taken as they are.
+fn unquote(raw: &str) -> Cow<'_, str> {
+    match raw.strip_prefix('"').and_then(|raw| raw.strip_suffix('"')) {
+        Some(inner) if inner.contains("\"\"") => Cow::Owned(inner.replace("\"\"", "\"")),
+        Some(inner) => Cow::Borrowed(inner),
+        None => Cow::Borrowed(raw),
+    }
+}
+
+/// Arguments of a command, parsed according to its schema
+pub(crate) struct Args<'a> {
+    pub syntax: Syntax,
+    raw: &'a [&'a str],
+    parsed: Vec<Option<Parsed<'a>>>,
+}
+
+impl<'a> Args<'a> {
+    /// Value of an optional argument if it was passed, e.g.
+    /// `args.optional(3, Args::seconds)`
+    pub fn optional<'s, T>(&'s self, index: usize, get: fn(&'s Self, usize) -> T) -> Option<T> {
+        match self.parsed.get(index) {
+            Some(Some(_)) => Some(get(self, index)),
+            _ => None,
+        }
+    }
+
+    pub fn string(&self, index: usize) -> &str {
+        match self.get(index) {
+            Parsed::String(string) => string,
+            _ => self.mismatch(index),
+        }
+    }
+
+    pub fn value(&self, index: usize) -> Value {
+        match self.get(index) {
+            Parsed::Value(value) => value.clone(),
+            _ => self.mismatch(index),
+        }
+    }
+
+    pub fn array(&self, index: usize) -> Vec<Value> {
+        match self.get(index) {
+            Parsed::Value(Value::Array(array)) => array.clone(),
+            _ => self.mismatch(index),
+        }
+    }
+
+    pub fn number(&self, index: usize) -> f32 {
+        match self.get(index) {
+            Parsed::Number(number) => *number,
+            _ => self.mismatch(index),
+        }
+    }
+
+    pub fn index(&self, index: usize) -> usize {
+        match self.get(index) {
+            Parsed::Index(value) => *value,
+            _ => self.mismatch(index),
+        }
+    }
+
+    pub fn seconds(&self, index: usize) -> Duration {
+        match self.get(index) {
+            Parsed::Seconds(duration) => *duration,
+            _ => self.mismatch(index),
+        }
+    }
+
+    pub fn transaction(&self, index: usize) -> u32 {
+        match self.get(index) {
+            Parsed::Transaction(id) => *id,
+            _ => self.mismatch(index),
+        }
+    }
+
+    /// Stringified arguments from `index` on, for variadic commands
+    pub fn rest(&self, index: usize) -> &'a [&'a str] {
+        self.raw.get(index..).unwrap_or_default()
+    }
+
+    fn get(&self, index: usize) -> &Parsed<'a> {
+        self.parsed
+            .get(index)
+            .and_then(Option::as_ref)
+            .unwrap_or_else(|| self.mismatch(index))
+    }
+
+    /// Handlers only read arguments their schema declares
+    fn mismatch(&self, index: usize) -> ! {
+        panic!("argument {} does not match the schema", index)
+    }
+}
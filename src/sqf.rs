@@ -0,0 +1,281 @@
+//! SQF function library for missions
+//!
+//! Every function of the standard syntax gets a wrapper `AS_fnc_<name>`
+//! taking the arguments of the function as an array and returning its parsed
+//! result. All of them go through `AS_fnc_call`, which handles error codes and
+//! fetches chunked results. `AS_fnc_addCallback` dispatches the
+//! `ExtensionCallback` event handler to handlers per callback function.
+//!
+//! The library is generated from the command registry of the extension, so
+//! the wrappers can not drift from the functions they call.
+use crate::{callback, extension::COMMANDS, registry::Command};
+
+const DIRECTORY: &str = "arma_storage";
+const GENERATED: &str = "// Generated by `arma-storage-cli sqf`, do not edit";
+
+/// Files of the library as relative paths and contents
+pub fn sqf_library() -> Vec<(String, String)> {
+    let wrappers: Vec<&Command> = COMMANDS
+        .iter()
+        // chunks are fetched by AS_fnc_call
+        .filter(|command| command.name != "chunk")
+        .collect();
+
+    let mut files = vec![
+        (String::from("CfgFunctions.hpp"), cfg_functions(&wrappers)),
+        function_file("call", CALL),
+        function_file("initCallbacks", INIT_CALLBACKS),
+        function_file("addCallback", &add_callback()),
+        function_file("removeCallback", REMOVE_CALLBACK),
+    ];
+
+    files.extend(
+        wrappers
+            .iter()
+            .map(|command| function_file(command.name, &wrapper(command))),
+    );
+
+    files
+}
+
+fn function_file(name: &str, contents: &str) -> (String, String) {
+    (
+        format!("functions/fn_{}.sqf", name),
+        format!("{}\n{}", GENERATED, contents),
+    )
+}
+
+fn cfg_functions(wrappers: &[&Command]) -> String {
+    let mut classes = vec![
+        String::from("            class call {};"),
+        String::from("            class initCallbacks { postInit = 1; };"),
+        String::from("            class addCallback {};"),
+        String::from("            class removeCallback {};"),
+    ];
+    classes.extend(
+        wrappers
+            .iter()
+            .map(|command| format!("            class {} {{}};", command.name)),
+    );
+
+    format!(
+        "{generated}
+// Copy the library into the mission as {directory} and add to description.ext:
+// #include \"{directory}\\CfgFunctions.hpp\"
+class CfgFunctions {{
+    class AS {{
+        tag = \"AS\";
+        class {directory} {{
+            file = \"{directory}\\functions\";
+{classes}
+        }};
+    }};
+}};
+",
+        generated = GENERATED,
+        directory = DIRECTORY,
+        classes = classes.join("\n")
+    )
+}
+
+fn wrapper(command: &Command) -> String {
+    let mut arguments: String = command
+        .args
+        .iter()
+        .enumerate()
+        .map(|(index, arg)| {
+            let optional = if arg.optional { " (optional)" } else { "" };
+            format!(
+                " * {}: {} <{}>{}\n",
+                index,
+                arg.name,
+                arg.kind.sqf_type(),
+                optional
+            )
+        })
+        .collect();
+
+    if command.variadic {
+        arguments.push_str(&format!(
+            " * {}...: Any further arguments\n",
+            command.args.len()
+        ));
+    } else if arguments.is_empty() {
+        arguments.push_str(" * None\n");
+    }
+
+    let example: Vec<String> = command
+        .args
+        .iter()
+        .filter(|arg| !arg.optional)
+        .map(|arg| format!("_{}", arg.name))
+        .collect();
+
+    format!(
+        "/*
+ * {description}
+ *
+ * Arguments:
+{arguments} *
+ * Return Value:
+ * Result of {name}, nil if it failed with the error in AS_lastError
+ *
+ * Example:
+ * [{example}] call AS_fnc_{name}
+ *
+ * Public: Yes
+ */
+[\"{name}\", _this] call AS_fnc_call
+",
+        description = command.description,
+        arguments = arguments,
+        name = command.name,
+        example = example.join(", ")
+    )
+}
+
+fn add_callback() -> String {
+    let events: String = callback::EVENTS
+        .iter()
+        .map(|(name, description)| format!(" * - {}: {}\n", name, description))
+        .collect();
+
+    format!(
+        "/*
+ * Run code whenever the extension raises a callback
+ *
+ * Callbacks:
+{events} *
+ * Arguments:
+ * 0: Callback function <STRING>
+ * 1: Code called with [data, function] <CODE>
+ *
+ * Return Value:
+ * Id to remove the handler with <NUMBER>
+ *
+ * Example:
+ * [\"storageChanged\", {{ systemChat format [\"%1 reloaded\", _this select 0] }}] call AS_fnc_addCallback
+ *
+ * Public: Yes
+ */
+params [[\"_function\", \"\", [\"\"]], [\"_code\", {{}}, [{{}}]]];
+
+if (isNil \"AS_callbacks\") then {{
+    call AS_fnc_initCallbacks;
+}};
+
+AS_callbackId = (missionNamespace getVariable [\"AS_callbackId\", 0]) + 1;
+(AS_callbacks getOrDefault [_function, createHashMap, true]) set [AS_callbackId, _code];
+
+AS_callbackId
+",
+        events = events
+    )
+}
+
+const CALL: &str = r#"/*
+ * Call a function of the extension and parse its result. Results too large
+ * for one call are fetched in chunks.
+ *
+ * Arguments:
+ * 0: Function <STRING>
+ * 1: Arguments <ARRAY>
+ *
+ * Return Value:
+ * Result of the function, nil if it failed. AS_lastError is then the error
+ * [code, identifier, message, context], otherwise [].
+ *
+ * Example:
+ * ["get", ["spam", "money"]] call AS_fnc_call
+ *
+ * Public: Yes
+ */
+params [["_function", "", [""]], ["_arguments", []]];
+
+if !(_arguments isEqualType []) then {
+    _arguments = [_arguments];
+};
+
+("arma_storage" callExtension [_function, _arguments]) params ["_result", "_code", "_armaError"];
+
+if (_armaError != 0) exitWith {
+    AS_lastError = [-1, "callExtensionFailed", format ["callExtension failed with error %1", _armaError], _function];
+    diag_log format ["[Arma Storage] %1 failed: %2", _function, AS_lastError];
+    nil
+};
+
+if (_code == 3) then {
+    (parseSimpleArray _result) params ["_id", "_count", "_resultCode"];
+
+    _result = "";
+    for "_index" from 0 to _count - 1 do {
+        ("arma_storage" callExtension ["chunk", [_id, _index]]) params ["_chunk", "_chunkCode", "_chunkArmaError"];
+
+        if (_chunkArmaError != 0) exitWith {
+            _result = str [-1, "callExtensionFailed", format ["callExtension failed with error %1", _chunkArmaError], "chunk"];
+            _resultCode = -1;
+        };
+        if (_chunkCode != 0) exitWith {
+            _result = _chunk;
+            _resultCode = _chunkCode;
+        };
+
+        _result = _result + ((parseSimpleArray ("[" + _chunk + "]")) select 0);
+    };
+    _code = _resultCode;
+};
+
+private _value = (parseSimpleArray ("[" + _result + "]")) select 0;
+
+if (_code != 0) exitWith {
+    AS_lastError = _value;
+    diag_log format ["[Arma Storage] %1 failed: %2", _function, _value];
+    nil
+};
+
+AS_lastError = [];
+_value
+"#;
+
+const INIT_CALLBACKS: &str = r#"/*
+ * Dispatch the ExtensionCallback event handler to the handlers added with
+ * AS_fnc_addCallback. Runs after mission start.
+ *
+ * Public: No
+ */
+if (!isNil "AS_callbacks") exitWith {};
+
+AS_callbacks = createHashMap;
+
+addMissionEventHandler ["ExtensionCallback", {
+    params ["_name", "_function", "_data"];
+
+    if (_name != "arma_storage") exitWith {};
+
+    {
+        [_data, _function] call _y;
+    } forEach (AS_callbacks getOrDefault [_function, createHashMap]);
+}];
+"#;
+
+const REMOVE_CALLBACK: &str = r#"/*
+ * Remove a handler added with AS_fnc_addCallback
+ *
+ * Arguments:
+ * 0: Callback function <STRING>
+ * 1: Id of the handler <NUMBER>
+ *
+ * Return Value:
+ * None
+ *
+ * Example:
+ * ["storageChanged", _id] call AS_fnc_removeCallback
+ *
+ * Public: Yes
+ */
+params [["_function", "", [""]], ["_id", 0, [0]]];
+
+if (isNil "AS_callbacks") exitWith {};
+
+(AS_callbacks getOrDefault [_function, createHashMap]) deleteAt _id;
+"#;
@@ -1,15 +1,38 @@
-use crate::Value;
+use crate::{
+    audit::AuditLog,
+    encryption::EncryptionKey,
+    format::{
+        read_file, salvage_file, verify_file, write_file, Compression, Contents, Verification,
+        TEMP_SUFFIX,
+    },
+    journal::{self, Record, JOURNAL_SUFFIX},
+    limits::{Change, Limits, Stats},
+    lock::{self, lock, LockMode, LOCK_SUFFIX},
+    transaction::{Operation, Transaction},
+    watch::{ConflictMode, FileStamp},
+    Value,
+};
 use anyhow::{ensure, Context, Result};
-use bincode::{deserialize_from, serialize_into};
-use log::info;
-use serde::{Deserialize, Serialize};
+use log::{error, info, warn};
 use std::{
     collections::HashMap,
-    fs::File,
+    fs::{self, File},
+    io, mem,
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 use thiserror::Error;
 
+/// Suffix of the copy [`StoragePool::salvage`] keeps of a damaged file
+const DAMAGED_SUFFIX: &str = ".damaged";
+
+/// Transaction ids go up to this and start over, staying exact as SQF numbers
+const MAX_TRANSACTION_ID: u32 = 1 << 24;
+
 #[derive(Debug, Error)]
 pub enum StorageError {
     #[error("Storage file is open")]
@@ -26,12 +49,89 @@ pub enum StorageError {
 
     #[error("Could not Serialize Storage")]
     Serialize,
+
+    #[error("Text is not an array of [key, value] pairs")]
+    Import,
+
+    #[error("Storage {0} is locked by another process")]
+    LockedByOtherProcess(String),
+
+    #[error("Storage {0} is opened shared and can not be written")]
+    ReadOnly(String),
+
+    #[error("Storage {0} was changed on disk since it was read")]
+    ModifiedOnDisk(String),
+
+    #[error("Limit of {max} {limit} exceeded")]
+    LimitExceeded { limit: &'static str, max: usize },
+
+    #[error("Storage name {0:?} is not a valid file name")]
+    InvalidName(String),
+
+    #[error("Storage file has unsupported format version {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("Storage file has unsupported compression {0}")]
+    UnsupportedCompression(u8),
+
+    #[error("Storage file has unsupported encryption {0}")]
+    UnsupportedEncryption(u8),
+
+    #[error("Storage file is damaged, its checksum does not match")]
+    ChecksumMismatch,
+
+    #[error("Storage file is truncated")]
+    Truncated,
+
+    #[error("Storage file is encrypted and no key is set")]
+    KeyRequired,
+
+    #[error("Storage file could not be decrypted, the key is wrong or the file is damaged")]
+    WrongKey,
+
+    #[error("Audit log is not enabled")]
+    AuditDisabled,
+
+    #[error("Value of key {key} is {found} but {expected} is required")]
+    TypeMismatch {
+        key: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+
+    #[error("Transaction {0} does not exist")]
+    UnknownTransaction(u32),
+
+    #[error("Index {index} is out of bounds for key {key} with length {len}")]
+    IndexOutOfBounds {
+        key: String,
+        index: usize,
+        len: usize,
+    },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Storage {
     name: String,
-    data: HashMap<String, Value>,
+    pub(crate) data: HashMap<String, Value>,
+    /// Unix timestamps in milliseconds after which a key is expired
+    pub(crate) expiry: HashMap<String, u64>,
+    lock_mode: LockMode,
+    /// File as it was last read or written
+    stamp: Option<FileStamp>,
+    /// Bytes of all keys and values, kept up to date by every change of `data`
+    size: usize,
+    /// Overrides the compression of the pool
+    compression: Option<Compression>,
+    /// Compression of the file when it was read, kept if nothing else is set
+    file_compression: Compression,
+    /// Whether the data was changed since the storage was last read or written
+    dirty: bool,
+    /// Unix timestamps in milliseconds of the last read and write
+    last_read: Option<u64>,
+    last_write: Option<u64>,
+    /// Set when the storage is closed while someone still holds it
+    closed: bool,
 }
 
 impl Storage {
@@ -39,14 +139,250 @@ impl Storage {
         Self {
             name: name.to_owned(),
             data: HashMap::new(),
+            expiry: HashMap::new(),
+            lock_mode: LockMode::Unlocked,
+            stamp: None,
+            size: 0,
+            compression: None,
+            file_compression: Compression::None,
+            dirty: false,
+            last_read: None,
+            last_write: None,
+            closed: false,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn lock_mode(&self) -> LockMode {
+        self.lock_mode
+    }
+
+    /// Bytes all keys and values take up in the storage file
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Estimated bytes the storage takes up in memory
+    pub fn memory_size(&self) -> usize {
+        // hash maps keep their entries in a table with a control byte each
+        let tables = self.data.capacity() * (mem::size_of::<(String, Value)>() + 1)
+            + self.expiry.capacity() * (mem::size_of::<(String, u64)>() + 1);
+        let data: usize = self
+            .data
+            .iter()
+            .map(|(key, value)| key.capacity() + value.heap_size())
+            .sum();
+        let expiry: usize = self.expiry.keys().map(String::capacity).sum();
+
+        mem::size_of::<Self>() + self.name.capacity() + tables + data + expiry
+    }
+
+    /// Whether the storage has changes that are not written yet
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Unix timestamp in milliseconds the storage was last read from its file
+    pub fn last_read(&self) -> Option<u64> {
+        self.last_read
+    }
+
+    /// Unix timestamp in milliseconds the storage was last written to its file
+    pub fn last_write(&self) -> Option<u64> {
+        self.last_write
+    }
+
+    pub(crate) fn insert(&mut self, key: String, value: Value) -> Option<Value> {
+        if let Some(old) = self.data.get(&key) {
+            self.size -= entry_size(&key, old);
+        }
+        self.size += entry_size(&key, &value);
+        self.dirty = true;
+
+        self.data.insert(key, value)
+    }
+
+    pub(crate) fn remove(&mut self, key: &str) -> Option<Value> {
+        let old = self.data.remove(key);
+
+        if let Some(old) = &old {
+            self.size -= entry_size(key, old);
+            self.dirty = true;
+        }
+
+        old
+    }
+
+    /// Replace all data
+    pub(crate) fn set_data(&mut self, data: HashMap<String, Value>) {
+        self.size = data_size(&data);
+        self.data = data;
+        self.dirty = true;
+    }
+
+    /// Value of a key unless it is missing or expired
+    pub fn value(&self, key: &str) -> Option<&Value> {
+        if self.is_expired(key) {
+            None
+        } else {
+            self.data.get(key)
+        }
+    }
+
+    pub fn is_expired(&self, key: &str) -> bool {
+        self.expiry
+            .get(key)
+            .is_some_and(|&expires| expires <= now_millis())
+    }
+
+    /// Remaining lifetime of a key. `None` if the key never expires.
+    pub fn ttl(&self, key: &str) -> Option<Duration> {
+        self.expiry
+            .get(key)
+            .map(|&expires| Duration::from_millis(expires.saturating_sub(now_millis())))
+    }
+
+    /// Let a key expire after `ttl` or never if `None`
+    pub fn set_ttl(&mut self, key: &str, ttl: Option<Duration>) {
+        self.set_expiry(key, ttl.map(expires_after));
+    }
+
+    /// Let a key expire at a Unix timestamp in milliseconds or never if `None`
+    pub(crate) fn set_expiry(&mut self, key: &str, expires: Option<u64>) {
+        match expires {
+            Some(expires) => {
+                self.expiry.insert(key.to_owned(), expires);
+            }
+            None => {
+                self.expiry.remove(key);
+            }
+        }
+    }
+
+    /// Remove a key if it has expired
+    pub fn purge(&mut self, key: &str) {
+        if self.is_expired(key) {
+            self.remove(key);
+            self.expiry.remove(key);
+        }
+    }
+
+    /// Remove all expired keys and return how many were removed
+    pub fn sweep(&mut self) -> usize {
+        let now = now_millis();
+        let expired: Vec<String> = self
+            .expiry
+            .iter()
+            .filter(|(_, &expires)| expires <= now)
+            .map(|(key, _)| key.to_owned())
+            .collect();
+
+        for key in &expired {
+            self.remove(key);
+            self.expiry.remove(key);
+        }
+
+        expired.len()
+    }
+}
+
+/// Bytes of a key and its value. The key is counted without its length prefix
+/// so the size of a storage is the sum of its entries.
+fn entry_size(key: &str, value: &Value) -> usize {
+    key.len() + value.size()
+}
+
+fn data_size(data: &HashMap<String, Value>) -> usize {
+    data.iter().map(|(key, value)| entry_size(key, value)).sum()
+}
+
+/// Unix timestamp in milliseconds `ttl` from now
+fn expires_after(ttl: Duration) -> u64 {
+    now_millis().saturating_add(ttl.as_millis().min(u128::from(u64::MAX)) as u64)
+}
+
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_millis() as u64)
+}
+
+/// UTC time like `2026-10-19T12:34:56.789Z` of a Unix timestamp in
+/// milliseconds
+pub(crate) fn format_time(millis: u64) -> String {
+    let (days, millis) = ((millis / 86_400_000) as i64, millis % 86_400_000);
+
+    // civil date from days since 1970-01-01, see
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// Selects keys when listing a storage
+#[derive(Debug)]
+pub enum KeyFilter {
+    All,
+    Prefix(String),
+    /// `*` matches any number of characters, `?` exactly one
+    Glob(String),
+}
+
+impl KeyFilter {
+    pub fn matches(&self, key: &str) -> bool {
+        match self {
+            KeyFilter::All => true,
+            KeyFilter::Prefix(prefix) => key.starts_with(prefix.as_str()),
+            KeyFilter::Glob(pattern) => glob_match(pattern, key),
         }
     }
 }
 
+/// Open storages. Every storage has its own lock so unrelated storages can be
+/// used in parallel, e.g. reading one while a huge one is written. See
+/// `benches/contention.rs` for numbers.
 #[derive(Debug)]
 pub struct StoragePool {
     path: PathBuf,
-    files: HashMap<String, Storage>,
+    files: RwLock<HashMap<String, Arc<RwLock<Storage>>>>,
+    transactions: Mutex<HashMap<u32, Transaction>>,
+    next_transaction: AtomicU32,
+    /// Held until the storage is closed
+    locks: Mutex<HashMap<String, File>>,
+    lock_mode: LockMode,
+    conflict_mode: ConflictMode,
+    limits: Limits,
+    /// Size of all open storages
+    total_size: AtomicUsize,
+    /// Compression of all storages. `None` keeps the compression of each file.
+    compression: Option<Compression>,
+    /// Encrypts all written files if set
+    key: Option<EncryptionKey>,
+    /// Whether changes are journaled before they are applied
+    journal: bool,
+    /// Logs all changes if set
+    audit: Option<AuditLog>,
 }
 
 impl StoragePool {
@@ -56,96 +392,966 @@ impl StoragePool {
     {
         Self {
             path: path.as_ref().into(),
-            files: HashMap::new(),
+            files: RwLock::new(HashMap::new()),
+            transactions: Mutex::new(HashMap::new()),
+            next_transaction: AtomicU32::new(1),
+            locks: Mutex::new(HashMap::new()),
+            lock_mode: LockMode::default(),
+            conflict_mode: ConflictMode::default(),
+            limits: Limits::default(),
+            total_size: AtomicUsize::new(0),
+            compression: None,
+            key: None,
+            journal: false,
+            audit: None,
         }
     }
 
-    pub fn open(&mut self, name: &str) -> Result<()> {
-        ensure!(!self.files.contains_key(name), StorageError::StorageIsOpen);
+    /// Lock mode used by [`open`](StoragePool::open)
+    pub fn set_lock_mode(&mut self, mode: LockMode) {
+        self.lock_mode = mode;
+    }
 
-        let storage = Storage::new(name);
+    /// What [`write`](StoragePool::write) does if the file was changed by
+    /// someone else since it was read
+    pub fn set_conflict_mode(&mut self, mode: ConflictMode) {
+        self.conflict_mode = mode;
+    }
 
-        self.files.insert(name.to_owned(), storage);
+    /// Compression of storages written without their own compression
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = Some(compression);
+    }
+
+    /// Compression of a storage on the next write. `None` uses the
+    /// compression of the pool, or keeps the one of the file if the pool has
+    /// none either.
+    pub fn set_storage_compression(
+        &self,
+        name: &str,
+        compression: Option<Compression>,
+    ) -> Result<()> {
+        self.with_storage_mut(name, |storage| {
+            storage.compression = compression;
+
+            Ok(())
+        })
+    }
+
+    /// Encrypt storage files with `key` when writing and decrypt them when
+    /// reading. Unencrypted files can still be read with a warning and get
+    /// encrypted on the next write.
+    pub fn set_key(&mut self, key: Option<EncryptionKey>) {
+        self.key = key;
+    }
+
+    /// Append every change to a journal on disk before applying it, so no
+    /// change is lost in a crash even if the storage is not written
+    pub fn set_journal(&mut self, enabled: bool) {
+        self.journal = enabled;
+    }
+
+    /// Log every change with its old and new value to the audit log in the
+    /// storage directory
+    pub fn set_audit(&mut self, enabled: bool) {
+        self.audit = enabled.then(|| AuditLog::new(&self.path));
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Lock mode used by [`open`](StoragePool::open)
+    pub fn lock_mode(&self) -> LockMode {
+        self.lock_mode
+    }
+
+    pub fn conflict_mode(&self) -> ConflictMode {
+        self.conflict_mode
+    }
+
+    /// Compression of storages written without their own compression, `None`
+    /// if each file keeps its compression
+    pub fn compression(&self) -> Option<Compression> {
+        self.compression
+    }
+
+    /// Whether written files are encrypted
+    pub fn is_encrypted(&self) -> bool {
+        self.key.is_some()
+    }
+
+    pub fn journal_enabled(&self) -> bool {
+        self.journal
+    }
+
+    pub fn audit_enabled(&self) -> bool {
+        self.audit.is_some()
+    }
+
+    pub fn open(&self, name: &str) -> Result<()> {
+        self.open_with(name, self.lock_mode)
+    }
+
+    /// Open a storage and lock its file against other processes
+    pub fn open_with(&self, name: &str, mode: LockMode) -> Result<()> {
+        let mut files = self.files.write().unwrap();
+
+        ensure!(!files.contains_key(name), StorageError::StorageIsOpen);
+        ensure!(
+            is_valid_name(name),
+            StorageError::InvalidName(name.to_owned())
+        );
+
+        if let Some(max) = self.limits.max_open_storages {
+            ensure!(
+                files.len() < max,
+                StorageError::LimitExceeded {
+                    limit: "open storages",
+                    max
+                }
+            );
+        }
 
         let storage_path = self.path.join(name);
+
+        if let Some(file) = lock(&storage_path, name, mode)? {
+            self.locks.lock().unwrap().insert(name.to_owned(), file);
+        }
+
+        if self.journal && mode != LockMode::Shared {
+            journal::repair(&storage_path)?;
+        }
+
+        let mut storage = Storage::new(name);
+        storage.lock_mode = mode;
+
+        files.insert(name.to_owned(), Arc::new(RwLock::new(storage)));
+
         info!("Opened storage at {}", storage_path.display());
 
         Ok(())
     }
 
-    pub fn close(&mut self, name: &str) -> Result<()> {
-        ensure!(self.files.contains_key(name), StorageError::StorageIsClosed);
-
-        self.files.remove(name);
+    pub fn close(&self, name: &str) -> Result<()> {
+        let removed = self
+            .files
+            .write()
+            .unwrap()
+            .remove(name)
+            .context(StorageError::StorageIsClosed)?;
+        // waits for running changes, later ones see the storage closed
+        let mut removed = removed.write().unwrap();
+        removed.closed = true;
+        self.resize(removed.size(), 0);
 
         let storage_path = self.path.join(name);
+
+        // changes that were not written are discarded with the storage
+        if self.journal && removed.lock_mode() != LockMode::Shared {
+            if let Err(err) = journal::remove(&storage_path) {
+                error!("Could not remove journal of storage {}: {:#}", name, err);
+            }
+        }
+
+        drop(removed);
+
+        self.locks.lock().unwrap().remove(name);
+        self.transactions
+            .lock()
+            .unwrap()
+            .retain(|_, transaction| transaction.storage() != name);
+
         info!("Closed storage at {}", storage_path.display());
 
         Ok(())
     }
 
-    pub fn read(&mut self, name: &str) -> Result<()> {
-        ensure!(self.files.contains_key(name), StorageError::StorageIsClosed);
+    pub fn read(&self, name: &str) -> Result<()> {
+        let storage = self.storage(name)?;
+        // changes made while the file is read would be lost
+        let mut storage = storage.write().unwrap();
+        ensure!(!storage.closed, StorageError::StorageIsClosed);
 
         let storage_path = self.path.join(name);
-        let file = File::open(&storage_path)?;
-        let data = deserialize_from(file).context(StorageError::Deserialize)?;
-
-        self.files
-            .get_mut(name)
-            .context(StorageError::StorageIsClosed)?
-            .data = data;
+        let records = journal::read(&storage_path, self.key.as_ref())?;
+        let (contents, stamp) = match read_file(&storage_path, self.key.as_ref()) {
+            Ok((contents, stamp)) => (contents, Some(stamp)),
+            // changes journaled before the storage was first written
+            Err(err) if !records.is_empty() && is_not_found(&err) => (Contents::default(), None),
+            Err(err) => return Err(err),
+        };
+        self.replace(&mut storage, contents, stamp, records);
 
         info!("Read storage at {}", storage_path.display());
 
         Ok(())
     }
 
-    pub fn write(&self, name: &str) -> Result<()> {
-        ensure!(self.files.contains_key(name), StorageError::StorageIsClosed);
+    /// Check the file of a storage for damage. The storage does not need to
+    /// be open.
+    pub fn verify(&self, name: &str) -> Result<Verification> {
+        ensure!(
+            is_valid_name(name),
+            StorageError::InvalidName(name.to_owned())
+        );
+
+        verify_file(&self.path.join(name), self.key.as_ref())
+    }
+
+    /// Read as many keys as possible from a damaged storage file and return
+    /// how many were recovered. The damaged file is copied to
+    /// `<name>.damaged` first, writing the storage then replaces it.
+    pub fn salvage(&self, name: &str) -> Result<usize> {
+        let storage = self.storage(name)?;
+        let mut storage = storage.write().unwrap();
+        ensure!(!storage.closed, StorageError::StorageIsClosed);
 
         let storage_path = self.path.join(name);
-        let file = File::create(&storage_path)?;
-        let data = &self
-            .files
-            .get(name)
-            .context(StorageError::StorageIsClosed)?
-            .data;
+        let mut damaged_path = storage_path.clone().into_os_string();
+        damaged_path.push(DAMAGED_SUFFIX);
+        fs::copy(&storage_path, &damaged_path)?;
+
+        let (contents, stamp) = salvage_file(&storage_path, self.key.as_ref())?;
+        let keys = contents.data.len();
+        let records = journal::read(&storage_path, self.key.as_ref())?;
+        self.replace(&mut storage, contents, Some(stamp), records);
+        // the recovered keys are not in the damaged file
+        storage.dirty = true;
+
+        warn!(
+            "Salvaged {} keys from storage at {}",
+            keys,
+            storage_path.display()
+        );
+
+        Ok(keys)
+    }
+
+    /// Replace the keys of a storage with the contents of its file and the
+    /// changes journaled since
+    fn replace(
+        &self,
+        storage: &mut Storage,
+        contents: Contents,
+        stamp: Option<FileStamp>,
+        records: Vec<Record>,
+    ) {
+        let size = storage.size();
+        storage.set_data(contents.data);
+        storage.expiry = contents.expiry;
+        storage.file_compression = contents.compression;
+        storage.stamp = stamp;
+        storage.dirty = false;
+        storage.last_read = Some(now_millis());
+        storage.replay(records);
+        storage.sweep();
+        self.resize(size, storage.size());
+    }
+
+    pub fn write(&self, name: &str) -> Result<()> {
+        let storage_path = self.path.join(name);
 
-        serialize_into(file, data).context(StorageError::Serialize)?;
+        self.with_storage_mut(name, |storage| {
+            ensure_writable(storage)?;
+            check_conflict(self.conflict_mode, storage, &storage_path)?;
+            let compression = self.compression_of(storage);
+            storage.stamp = Some(write_file(
+                &storage_path,
+                storage,
+                compression,
+                self.key.as_ref(),
+            )?);
+            storage.file_compression = compression;
+            storage.dirty = false;
+            storage.last_write = Some(now_millis());
+
+            journal::remove(&storage_path)
+        })?;
 
         info!("Wrote storage at {}", storage_path.display());
 
         Ok(())
     }
 
-    pub fn get(&self, name: &str, key: &str) -> Result<&Value> {
-        ensure!(self.files.contains_key(name), StorageError::StorageIsClosed);
+    /// Read all open storages whose files were changed by someone else and
+    /// return their names. Changes in memory are lost like with
+    /// [`read`](StoragePool::read). Storages that were never read or written
+    /// are left alone.
+    pub fn reload_changed(&self) -> Vec<String> {
+        let mut changed = Vec::new();
 
-        let data = &self.files.get(name).unwrap().data;
+        for storage in self.storages() {
+            let name = {
+                let storage = storage.read().unwrap();
+                let path = self.path.join(storage.name());
 
-        if let Some(value) = data.get(key) {
-            info!("Read storage {} key {}", name, key);
+                match &storage.stamp {
+                    Some(stamp)
+                        if stamp.may_be_changed(&path).unwrap_or(false)
+                            && stamp.is_changed(&path).unwrap_or(false) =>
+                    {
+                        storage.name().to_owned()
+                    }
+                    _ => continue,
+                }
+            };
 
-            Ok(value)
-        } else {
-            Err(StorageError::StorageMissingKey(key.to_owned()))?
+            match self.read(&name) {
+                Ok(()) => changed.push(name),
+                Err(err) => error!("Could not reload storage {}: {:#}", name, err),
+            }
         }
+
+        changed
+    }
+
+    /// Current usage of a storage and the whole pool
+    pub fn stats(&self, name: &str) -> Result<Stats> {
+        let (keys, size) =
+            self.with_storage(name, |storage| Ok((storage.data.len(), storage.size())))?;
+
+        Ok(Stats {
+            keys,
+            size,
+            total_size: self.total_size.load(Ordering::Relaxed),
+            open_storages: self.files.read().unwrap().len(),
+        })
     }
 
-    pub fn set(&mut self, name: &str, key: &str, value: &Value) -> Result<()> {
-        todo!()
+    pub fn get(&self, name: &str, key: &str) -> Result<Value> {
+        self.with_storage(name, |storage| {
+            if let Some(value) = storage.value(key) {
+                info!("Read storage {} key {}", name, key);
+
+                Ok(value.clone())
+            } else {
+                Err(StorageError::StorageMissingKey(key.to_owned()).into())
+            }
+        })
+    }
+
+    /// Set a key which expires after `ttl` or never if `None`
+    pub fn set(&self, name: &str, key: &str, value: &Value, ttl: Option<Duration>) -> Result<()> {
+        self.with_storage_mut(name, |storage| {
+            storage.purge(key);
+            self.check_limits(storage, &[(key, value)])?;
+
+            let expires = ttl.map(expires_after);
+            self.record(storage, || {
+                vec![Record::Set(key.to_owned(), value.clone(), expires)]
+            })?;
+
+            storage.insert(key.to_owned(), value.clone());
+            storage.set_expiry(key, expires);
+
+            Ok(())
+        })?;
+
+        info!("Set storage {} key {}", name, key);
+
+        Ok(())
     }
 
-    pub fn erase(&mut self, name: &str, key: &str) -> Result<()> {
-        todo!()
+    pub fn erase(&self, name: &str, key: &str) -> Result<()> {
+        self.with_storage_mut(name, |storage| {
+            storage.purge(key);
+            ensure!(
+                storage.data.contains_key(key),
+                StorageError::StorageMissingKey(key.to_owned())
+            );
+
+            self.record(storage, || vec![Record::Erase(key.to_owned())])?;
+
+            storage.expiry.remove(key);
+            storage.remove(key);
+
+            info!("Erased storage {} key {}", name, key);
+
+            Ok(())
+        })
     }
 
     pub fn exists(&self, name: &str, key: &str) -> Result<bool> {
-        todo!()
+        self.with_storage(name, |storage| Ok(storage.value(key).is_some()))
+    }
+
+    /// Remaining lifetime of a key. `None` if the key never expires.
+    pub fn ttl(&self, name: &str, key: &str) -> Result<Option<Duration>> {
+        self.with_storage(name, |storage| {
+            ensure!(
+                storage.value(key).is_some(),
+                StorageError::StorageMissingKey(key.to_owned())
+            );
+
+            Ok(storage.ttl(key))
+        })
+    }
+
+    /// Remove expired keys from all storages and return how many were removed
+    pub fn sweep(&self) -> usize {
+        let removed = self
+            .storages()
+            .iter()
+            .map(|storage| {
+                let mut storage = storage.write().unwrap();
+                if storage.closed {
+                    return 0;
+                }
+
+                let size = storage.size();
+                let removed = storage.sweep();
+                self.resize(size, storage.size());
+
+                removed
+            })
+            .sum();
+
+        if removed > 0 {
+            info!("Removed {} expired keys", removed);
+        }
+
+        removed
+    }
+
+    /// Get multiple keys at once. The outer error is returned if the storage
+    /// is not open, the inner ones for every missing key.
+    pub fn get_many(&self, name: &str, keys: &[String]) -> Result<Vec<Result<Value>>> {
+        let values = self.with_storage(name, |storage| {
+            Ok(keys
+                .iter()
+                .map(|key| {
+                    storage
+                        .value(key)
+                        .cloned()
+                        .ok_or_else(|| StorageError::StorageMissingKey(key.to_owned()).into())
+                })
+                .collect())
+        })?;
+
+        info!("Read storage {} keys {:?}", name, keys);
+
+        Ok(values)
+    }
+
+    /// Set multiple keys at once. Either all keys are set or, if the limits
+    /// are exceeded or journaling fails, none.
+    pub fn set_many(&self, name: &str, entries: &[(String, Value)]) -> Result<()> {
+        // a later entry replaces an earlier one with the same key
+        let entries: HashMap<&str, &Value> = entries
+            .iter()
+            .map(|(key, value)| (key.as_str(), value))
+            .collect();
+        let entries: Vec<(&str, &Value)> = entries.into_iter().collect();
+
+        self.with_storage_mut(name, |storage| {
+            for (key, _) in &entries {
+                storage.purge(key);
+            }
+            self.check_limits(storage, &entries)?;
+
+            self.record(storage, || {
+                entries
+                    .iter()
+                    .map(|(key, value)| Record::Set((*key).to_owned(), (*value).clone(), None))
+                    .collect()
+            })?;
+
+            for (key, value) in &entries {
+                storage.insert((*key).to_owned(), (*value).clone());
+                storage.expiry.remove(*key);
+            }
+
+            Ok(())
+        })?;
+
+        info!("Set storage {} {} keys", name, entries.len());
+
+        Ok(())
+    }
+
+    /// Replace the value of `key` with `new` only if it currently equals
+    /// `expected`. Returns whether the value was replaced. A missing key never
+    /// matches.
+    pub fn compare_and_set(
+        &self,
+        name: &str,
+        key: &str,
+        expected: &Value,
+        new: &Value,
+    ) -> Result<bool> {
+        self.with_storage_mut(name, |storage| {
+            storage.purge(key);
+
+            if !storage
+                .data
+                .get(key)
+                .is_some_and(|value| value.sqf_eq(expected))
+            {
+                return Ok(false);
+            }
+
+            self.check_limits(storage, &[(key, new)])?;
+            self.record(storage, || {
+                let expires = storage.expiry.get(key).copied();
+                vec![Record::Set(key.to_owned(), new.clone(), expires)]
+            })?;
+            storage.insert(key.to_owned(), new.clone());
+
+            info!("Swapped storage {} key {}", name, key);
+
+            Ok(true)
+        })
+    }
+
+    /// Start a transaction on a storage and return its id
+    pub fn begin(&self, name: &str) -> Result<u32> {
+        self.storage(name)?;
+
+        let mut transactions = self.transactions.lock().unwrap();
+
+        // ids stay exact as SQF numbers and skip those still in use
+        let id = loop {
+            let id = self
+                .next_transaction
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |id| {
+                    Some(id % MAX_TRANSACTION_ID + 1)
+                })
+                .unwrap();
+
+            if !transactions.contains_key(&id) {
+                break id;
+            }
+        };
+
+        transactions.insert(id, Transaction::new(name));
+
+        info!("Began transaction {} on storage {}", id, name);
+
+        Ok(id)
+    }
+
+    /// Add an operation to a transaction. Nothing is changed until commit.
+    pub fn stage(&self, id: u32, operation: Operation) -> Result<()> {
+        self.transactions
+            .lock()
+            .unwrap()
+            .get_mut(&id)
+            .context(StorageError::UnknownTransaction(id))?
+            .stage(operation);
+
+        Ok(())
+    }
+
+    /// Apply all operations of a transaction and write the storage.
+    ///
+    /// Only the transaction is written: the file gets the operations applied
+    /// to what is already on disk, so other changes not written yet stay in
+    /// memory only. If any operation or the write fails neither the storage
+    /// in memory nor the file is changed. The transaction is finished either
+    /// way.
+    pub fn commit(&self, id: u32) -> Result<()> {
+        let transaction = self
+            .transactions
+            .lock()
+            .unwrap()
+            .remove(&id)
+            .context(StorageError::UnknownTransaction(id))?;
+
+        let name = transaction.storage();
+        let storage_path = self.path.join(name);
+        let records = transaction.records();
+
+        self.with_storage_mut(name, |storage| {
+            ensure_writable(storage)?;
+            let mut updated = storage.applied(transaction.operations())?;
+
+            let values = transaction
+                .operations()
+                .iter()
+                .filter_map(|operation| match operation {
+                    Operation::Set(_, value) => Some(value),
+                    Operation::Erase(_) => None,
+                });
+            self.limits.check(self.change(storage, &updated), values)?;
+
+            check_conflict(self.conflict_mode, storage, &storage_path)?;
+            let mut persisted = self.persisted(storage, &storage_path)?;
+            // the audit log tells how the file changes
+            let before = self.audit.as_ref().map(|_| persisted.clone());
+            persisted.replay(records.clone());
+
+            let compression = self.compression_of(storage);
+            updated.stamp = Some(write_file(
+                &storage_path,
+                &persisted,
+                compression,
+                self.key.as_ref(),
+            )?);
+            updated.file_compression = compression;
+            // other changes not written yet stay in memory only
+            updated.dirty = updated.data != persisted.data || updated.expiry != persisted.expiry;
+            updated.last_write = Some(now_millis());
+
+            // the file holds everything journaled. A journal left behind is
+            // replayed on top of it, so it has to end with the transaction.
+            if let Err(err) = journal::remove(&storage_path) {
+                error!("Could not remove journal of storage {}: {:#}", name, err);
+
+                if let Err(err) = journal::append(&storage_path, self.key.as_ref(), &records) {
+                    error!("Could not journal transaction {}: {:#}", id, err);
+                }
+            }
+
+            if let (Some(audit), Some(before)) = (&self.audit, &before) {
+                audit.log(before, &records);
+            }
+
+            *storage = updated;
+
+            Ok(())
+        })?;
+
+        info!("Committed transaction {} on storage {}", id, name);
+
+        Ok(())
+    }
+
+    /// A storage as it is on disk: its file and the changes journaled since
+    fn persisted(&self, storage: &Storage, path: &Path) -> Result<Storage> {
+        let mut persisted = Storage::new(storage.name());
+
+        match read_file(path, self.key.as_ref()) {
+            Ok((contents, _)) => {
+                persisted.set_data(contents.data);
+                persisted.expiry = contents.expiry;
+            }
+            Err(err) if is_not_found(&err) => {}
+            Err(err) => return Err(err),
+        }
+
+        persisted.replay(journal::read(path, self.key.as_ref())?);
+        persisted.sweep();
+
+        Ok(persisted)
+    }
+
+    /// Discard a transaction
+    pub fn rollback(&self, id: u32) -> Result<()> {
+        self.transactions
+            .lock()
+            .unwrap()
+            .remove(&id)
+            .context(StorageError::UnknownTransaction(id))?;
+
+        info!("Rolled back transaction {}", id);
+
+        Ok(())
+    }
+
+    /// Sorted keys of a storage matching `filter`, skipping the first `offset`
+    /// and returning at most `limit` keys
+    pub fn keys(
+        &self,
+        name: &str,
+        filter: &KeyFilter,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> Result<Vec<String>> {
+        self.with_storage(name, |storage| {
+            let mut keys: Vec<&String> = storage
+                .data
+                .keys()
+                .filter(|key| filter.matches(key) && !storage.is_expired(key))
+                .collect();
+            keys.sort_unstable();
+
+            let keys = keys
+                .into_iter()
+                .skip(offset)
+                .take(limit.unwrap_or(usize::MAX))
+                .cloned()
+                .collect();
+
+            Ok(keys)
+        })
     }
 
-    pub fn get_files(&self) -> Vec<&str> {
-        self.files.keys().map(|key| key.as_str()).collect()
+    /// Directory the storage files are in
+    pub fn path(&self) -> &Path {
+        &self.path
     }
+
+    pub fn get_files(&self) -> Vec<String> {
+        self.files.read().unwrap().keys().cloned().collect()
+    }
+
+    /// Sorted names of all storage files in the storage directory, open or not
+    pub fn files_on_disk(&self) -> Result<Vec<String>> {
+        let mut files = Vec::new();
+
+        for entry in fs::read_dir(&self.path)? {
+            let entry = entry?;
+
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+
+            if let Some(name) = entry.file_name().to_str() {
+                if is_valid_name(name) {
+                    files.push(name.to_owned());
+                }
+            }
+        }
+
+        files.sort_unstable();
+
+        Ok(files)
+    }
+
+    /// Delete a storage file, its journal and its lock file. An open storage
+    /// stays in memory and can be written again and keeps its lock file. A
+    /// storage another process has locked is not deleted.
+    pub fn delete(&self, name: &str) -> Result<()> {
+        ensure!(
+            is_valid_name(name),
+            StorageError::InvalidName(name.to_owned())
+        );
+
+        let storage_path = self.path.join(name);
+
+        // a storage open in this pool holds its own lock
+        let lock = if self.files.read().unwrap().contains_key(name) {
+            None
+        } else {
+            lock::check(&storage_path, name)?
+        };
+
+        fs::remove_file(&storage_path)?;
+        journal::remove(&storage_path)?;
+
+        // nobody else holds the lock, so its file is not needed anymore
+        if lock.is_some() {
+            lock::remove(&storage_path)?;
+        }
+
+        info!("Deleted storage at {}", storage_path.display());
+
+        Ok(())
+    }
+
+    /// Run `f` with a storage locked for reading
+    pub(crate) fn with_storage<T>(
+        &self,
+        name: &str,
+        f: impl FnOnce(&Storage) -> Result<T>,
+    ) -> Result<T> {
+        let storage = self.storage(name)?;
+        let storage = storage.read().unwrap();
+        ensure!(!storage.closed, StorageError::StorageIsClosed);
+
+        f(&storage)
+    }
+
+    /// Run `f` with a storage locked for writing. Only this storage is locked.
+    pub(crate) fn with_storage_mut<T>(
+        &self,
+        name: &str,
+        f: impl FnOnce(&mut Storage) -> Result<T>,
+    ) -> Result<T> {
+        let storage = self.storage(name)?;
+        let mut storage = storage.write().unwrap();
+        ensure!(!storage.closed, StorageError::StorageIsClosed);
+
+        let size = storage.size();
+        let result = f(&mut storage);
+        self.resize(size, storage.size());
+
+        result
+    }
+
+    /// Journal and audit changes to `storage` before they are applied.
+    /// `records` is only called if either is enabled.
+    pub(crate) fn record(
+        &self,
+        storage: &Storage,
+        records: impl FnOnce() -> Vec<Record>,
+    ) -> Result<()> {
+        let journal = self.journal && storage.lock_mode() != LockMode::Shared;
+
+        if !journal && self.audit.is_none() {
+            return Ok(());
+        }
+
+        let records = records();
+
+        if journal {
+            journal::append(&self.path.join(storage.name()), self.key.as_ref(), &records)?;
+        }
+
+        if let Some(audit) = &self.audit {
+            audit.log(storage, &records);
+        }
+
+        Ok(())
+    }
+
+    /// The audit log if it is enabled
+    pub(crate) fn audit(&self) -> Result<&AuditLog> {
+        self.audit.as_ref().context(StorageError::AuditDisabled)
+    }
+
+    /// Make sure setting `entries` in `storage` stays within the limits
+    pub(crate) fn check_limits(&self, storage: &Storage, entries: &[(&str, &Value)]) -> Result<()> {
+        let mut keys = storage.data.len();
+        let mut size = storage.size();
+
+        for (key, value) in entries {
+            match storage.data.get(*key) {
+                Some(old) => size -= entry_size(key, old),
+                None => keys += 1,
+            }
+            size += entry_size(key, value);
+        }
+
+        let change = Change {
+            keys: (storage.data.len(), keys),
+            size: (storage.size(), size),
+            total_size: self.total_size.load(Ordering::Relaxed),
+        };
+
+        self.limits
+            .check(change, entries.iter().map(|(_, value)| *value))
+    }
+
+    /// Change from `storage` to `updated` for checking the limits
+    pub(crate) fn change(&self, storage: &Storage, updated: &Storage) -> Change {
+        Change {
+            keys: (storage.data.len(), updated.data.len()),
+            size: (storage.size(), updated.size()),
+            total_size: self.total_size.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Compression of the next write of `storage`
+    fn compression_of(&self, storage: &Storage) -> Compression {
+        storage
+            .compression
+            .or(self.compression)
+            .unwrap_or(storage.file_compression)
+    }
+
+    /// Account for a storage changing its size
+    fn resize(&self, before: usize, after: usize) {
+        // saturating so a miscount can never wrap around
+        let _ = self
+            .total_size
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |total| {
+                Some(total.saturating_add(after).saturating_sub(before))
+            });
+    }
+
+    /// The pool is only locked to look up the storage, not while it is used
+    fn storage(&self, name: &str) -> Result<Arc<RwLock<Storage>>> {
+        self.files
+            .read()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .context(StorageError::StorageIsClosed)
+    }
+
+    pub(crate) fn storages(&self) -> Vec<Arc<RwLock<Storage>>> {
+        self.files.read().unwrap().values().cloned().collect()
+    }
+}
+
+fn is_not_found(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<io::Error>()
+        .is_some_and(|err| err.kind() == io::ErrorKind::NotFound)
+}
+
+fn ensure_writable(storage: &Storage) -> Result<()> {
+    ensure!(
+        storage.lock_mode() != LockMode::Shared,
+        StorageError::ReadOnly(storage.name().to_owned())
+    );
+
+    Ok(())
+}
+
+/// Make sure writing `storage` to `path` does not overwrite changes someone
+/// else made to the file
+fn check_conflict(mode: ConflictMode, storage: &Storage, path: &Path) -> Result<()> {
+    // only hash the file if its modification time or size changed
+    let changed = match &storage.stamp {
+        Some(stamp) => stamp.may_be_changed(path)? && stamp.is_changed(path)?,
+        None => false,
+    };
+
+    if changed {
+        match mode {
+            ConflictMode::Refuse => {
+                return Err(StorageError::ModifiedOnDisk(storage.name().to_owned()).into())
+            }
+            ConflictMode::Warn => warn!(
+                "Overwriting changes made to storage {} on disk",
+                storage.name()
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+/// Storage names are file names inside the storage directory and must not
+/// point anywhere else or at the files kept next to a storage
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.contains(['/', '\\', ':', '\0'])
+        && ![TEMP_SUFFIX, LOCK_SUFFIX, DAMAGED_SUFFIX, JOURNAL_SUFFIX]
+            .iter()
+            .any(|suffix| name.ends_with(suffix))
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    // position of the last `*` and the text position it was tried at
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some('?') => {
+                p += 1;
+                t += 1;
+            }
+            Some(&c) if c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
 }
@@ -0,0 +1,79 @@
+use crate::{
+    journal::Record,
+    storage::{Storage, StorageError},
+    Value,
+};
+use anyhow::{ensure, Result};
+
+/// A change staged in a [`Transaction`]
+#[derive(Debug, Clone)]
+pub enum Operation {
+    Set(String, Value),
+    Erase(String),
+}
+
+/// Changes to a single storage that get applied all at once on commit
+#[derive(Debug)]
+pub struct Transaction {
+    storage: String,
+    operations: Vec<Operation>,
+}
+
+impl Transaction {
+    pub fn new(storage: &str) -> Self {
+        Self {
+            storage: storage.to_owned(),
+            operations: Vec::new(),
+        }
+    }
+
+    pub fn storage(&self) -> &str {
+        &self.storage
+    }
+
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    pub fn stage(&mut self, operation: Operation) {
+        self.operations.push(operation);
+    }
+
+    /// The operations as they are journaled
+    pub(crate) fn records(&self) -> Vec<Record> {
+        self.operations
+            .iter()
+            .map(|operation| match operation {
+                Operation::Set(key, value) => Record::Set(key.clone(), value.clone(), None),
+                Operation::Erase(key) => Record::Erase(key.clone()),
+            })
+            .collect()
+    }
+}
+
+impl Storage {
+    /// Copy of the storage after applying operations in order. `self` is never
+    /// modified so a failing operation leaves the storage untouched.
+    pub fn applied(&self, operations: &[Operation]) -> Result<Storage> {
+        let mut storage = self.clone();
+
+        for operation in operations {
+            match operation {
+                Operation::Set(key, value) => {
+                    storage.insert(key.to_owned(), value.clone());
+                    storage.expiry.remove(key);
+                }
+                Operation::Erase(key) => {
+                    storage.purge(key);
+                    storage.expiry.remove(key);
+                    ensure!(
+                        storage.remove(key).is_some(),
+                        StorageError::StorageMissingKey(key.to_owned())
+                    );
+                }
+            }
+        }
+
+        Ok(storage)
+    }
+}
@@ -1,7 +1,21 @@
+use pest::{iterators::Pair, Parser};
+use pest_derive::Parser;
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Parser)]
+#[grammar = "value.pest"]
+struct ValueParser;
+
+#[derive(Debug, Error)]
+pub enum ValueError {
+    #[error("Could not parse value: {0}")]
+    Parse(String),
+}
 
 // https://community.bistudio.com/wiki/Side
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub enum Side {
     Blufor = 1,
     Opfor = 0,
@@ -30,9 +44,25 @@ impl Side {
             Side::Logic => String::from("sideLogic"),
         }
     }
+
+    fn from_sqf(side: &str) -> Option<Self> {
+        match side.to_lowercase().as_str() {
+            "blufor" | "west" => Some(Side::Blufor),
+            "opfor" | "east" => Some(Side::Opfor),
+            "independent" | "resistance" | "guer" => Some(Side::Independent),
+            "civilian" | "civ" => Some(Side::Civilian),
+            "sideambientlife" | "ambient life" => Some(Side::AmbientLife),
+            "sideempty" | "empty" => Some(Side::Empty),
+            "sidefriendly" | "friendly" => Some(Side::Friendly),
+            "sideenemy" | "enemy" => Some(Side::Enemy),
+            "sideunknown" | "unknown" => Some(Side::Unknown),
+            "sidelogic" | "logic" => Some(Side::Logic),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub enum Value {
     Array(Vec<Value>),
     Boolean(bool),
@@ -56,6 +86,23 @@ pub enum Value {
 }
 
 impl Value {
+    /// Equality as seen from SQF, used to compare against values scripts
+    /// send. Numbers are equal if SQF would print them the same, because Arma
+    /// only sends numbers with six significant digits. `NaN` is equal to
+    /// itself so a stored `NaN` can be compared and swapped.
+    ///
+    /// Unlike `==` this is not transitive, so it is only used where one side
+    /// comes from a script.
+    pub fn sqf_eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Array(a), Value::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.sqf_eq(b))
+            }
+            (Value::Number(a), Value::Number(b)) => number_eq(*a, *b),
+            _ => self == other,
+        }
+    }
+
     pub fn as_sqf(&self) -> String {
         match self {
             Value::Array(array) => {
@@ -64,23 +111,125 @@ impl Value {
                 format!("[{}]", array.join(", "))
             }
             Value::Boolean(boolean) => format!("{}", boolean),
-            Value::Group(group) => format!("{}", group),
+            Value::Group(group) => group.clone(),
             Value::Number(number) => format!("{}", number),
-            Value::Object(object) => format!("{}", object),
+            Value::Object(object) => object.clone(),
             Value::Side(side) => side.as_sqf(),
-            Value::String(string) => format!("\"{}\"", string),
-            Value::Code(code) => format!("{}", code),
-            Value::Config(config) => format!("{}", config),
-            Value::Control(control) => format!("{}", control),
-            Value::Display(display) => format!("{}", display),
-            Value::Location(location) => format!("{}", location),
-            Value::ScriptHandle(script_handle) => format!("{}", script_handle),
-            Value::StructuredText(strucured_text) => format!("{}", strucured_text),
-            Value::DiaryRecord(diary_record) => format!("{}", diary_record),
-            Value::Task(task) => format!("{}", task),
-            Value::TeamMember(team_member) => format!("{}", team_member),
-            Value::Namespace(namespace) => format!("{}", namespace),
+            Value::String(string) => format!("\"{}\"", string.replace('"', "\"\"")),
+            Value::Code(code) => code.clone(),
+            Value::Config(config) => config.clone(),
+            Value::Control(control) => control.clone(),
+            Value::Display(display) => display.clone(),
+            Value::Location(location) => location.clone(),
+            Value::ScriptHandle(script_handle) => script_handle.clone(),
+            Value::StructuredText(strucured_text) => strucured_text.clone(),
+            Value::DiaryRecord(diary_record) => diary_record.clone(),
+            Value::Task(task) => task.clone(),
+            Value::TeamMember(team_member) => team_member.clone(),
+            Value::Namespace(namespace) => namespace.clone(),
             Value::Void => String::new(),
         }
     }
+
+    /// Name of the SQF type this value represents
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Array(_) => "ARRAY",
+            Value::Boolean(_) => "BOOL",
+            Value::Group(_) => "GROUP",
+            Value::Number(_) => "SCALAR",
+            Value::Object(_) => "OBJECT",
+            Value::Side(_) => "SIDE",
+            Value::String(_) => "STRING",
+            Value::Code(_) => "CODE",
+            Value::Config(_) => "CONFIG",
+            Value::Control(_) => "CONTROL",
+            Value::Display(_) => "DISPLAY",
+            Value::Location(_) => "LOCATION",
+            Value::ScriptHandle(_) => "SCRIPT",
+            Value::StructuredText(_) => "TEXT",
+            Value::DiaryRecord(_) => "DIARY_RECORD",
+            Value::Task(_) => "TASK",
+            Value::TeamMember(_) => "TEAM_MEMBER",
+            Value::Namespace(_) => "NAMESPACE",
+            Value::Void => "NOTHING",
+        }
+    }
+
+    /// Bytes the value takes up in a storage file
+    pub fn size(&self) -> usize {
+        bincode::serialized_size(self).map_or(0, |size| size as usize)
+    }
+
+    /// Bytes the value owns on the heap
+    pub(crate) fn heap_size(&self) -> usize {
+        match self {
+            Value::Array(array) => {
+                array.capacity() * std::mem::size_of::<Value>()
+                    + array.iter().map(Value::heap_size).sum::<usize>()
+            }
+            Value::Boolean(_) | Value::Number(_) | Value::Side(_) | Value::Void => 0,
+            Value::Group(string)
+            | Value::Object(string)
+            | Value::String(string)
+            | Value::Code(string)
+            | Value::Config(string)
+            | Value::Control(string)
+            | Value::Display(string)
+            | Value::Location(string)
+            | Value::ScriptHandle(string)
+            | Value::StructuredText(string)
+            | Value::DiaryRecord(string)
+            | Value::Task(string)
+            | Value::TeamMember(string)
+            | Value::Namespace(string) => string.capacity(),
+        }
+    }
+
+    fn from_pair(pair: Pair<Rule>) -> Self {
+        match pair.as_rule() {
+            Rule::array => Value::Array(pair.into_inner().map(Value::from_pair).collect()),
+            Rule::boolean => Value::Boolean(pair.as_str().eq_ignore_ascii_case("true")),
+            // the grammar only accepts valid floats
+            Rule::number => Value::Number(pair.as_str().parse().unwrap()),
+            Rule::side => Value::Side(Side::from_sqf(pair.as_str()).unwrap()),
+            Rule::string => {
+                let inner = pair.into_inner().next().unwrap();
+                let string = match inner.as_rule() {
+                    Rule::double_quoted => inner.as_str().replace("\"\"", "\""),
+                    _ => inner.as_str().replace("''", "'"),
+                };
+
+                Value::String(string)
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+fn number_eq(a: f32, b: f32) -> bool {
+    // also covers 0 == -0
+    if a == b {
+        return true;
+    }
+
+    if a.is_nan() || b.is_nan() {
+        return a.is_nan() && b.is_nan();
+    }
+
+    format!("{:.5e}", a) == format!("{:.5e}", b)
+}
+
+/// Parses a value stringified by Arma
+impl FromStr for Value {
+    type Err = ValueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let pair = ValueParser::parse(Rule::input, s)
+            .map_err(|err| ValueError::Parse(err.to_string()))?
+            .next()
+            .unwrap();
+
+        Ok(Value::from_pair(pair))
+    }
 }
@@ -0,0 +1,107 @@
+//! Detect changes other programs make to storage files
+//!
+//! Every read and write remembers the modification time, size and a hash of
+//! the file. Before the next write the content is compared against it if the
+//! modification time or size differ, so changes made by an admin tool are not
+//! silently overwritten. Watching for changes does the same.
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt,
+    fs::{self, File, Metadata},
+    hash::{Hash, Hasher},
+    io::{ErrorKind, Read},
+    path::Path,
+    str::FromStr,
+    time::SystemTime,
+};
+
+/// State of a storage file when it was last read or written
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+    hash: u64,
+}
+
+impl FileStamp {
+    pub fn new(metadata: &Metadata, bytes: &[u8]) -> Self {
+        Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            hash: hash(bytes),
+        }
+    }
+
+    /// Read a file and its stamp at the same time
+    pub fn read(path: &Path) -> std::io::Result<(Vec<u8>, Self)> {
+        let mut file = File::open(path)?;
+        let metadata = file.metadata()?;
+
+        let mut bytes = Vec::with_capacity(metadata.len() as usize);
+        file.read_to_end(&mut bytes)?;
+
+        let stamp = Self::new(&metadata, &bytes);
+
+        Ok((bytes, stamp))
+    }
+
+    /// Whether the content of the file was changed since the stamp was taken.
+    /// A missing file is not changed as there is nothing to overwrite or reload.
+    pub fn is_changed(&self, path: &Path) -> std::io::Result<bool> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(bytes.len() as u64 != self.len || hash(&bytes) != self.hash),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Cheap check of the modification time and size without reading the
+    /// file. File systems only update the modification time every few
+    /// milliseconds, so quick changes keeping the size can be missed.
+    pub fn may_be_changed(&self, path: &Path) -> std::io::Result<bool> {
+        match fs::metadata(path) {
+            Ok(metadata) => {
+                Ok(metadata.modified().ok() != self.modified || metadata.len() != self.len)
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// What to do when writing a storage whose file was changed by someone else
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ConflictMode {
+    /// Fail the write and leave the file alone
+    #[default]
+    Refuse,
+    /// Log a warning and overwrite the file anyway
+    Warn,
+}
+
+impl FromStr for ConflictMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "refuse" => Ok(ConflictMode::Refuse),
+            "warn" => Ok(ConflictMode::Warn),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for ConflictMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ConflictMode::Refuse => "refuse",
+            ConflictMode::Warn => "warn",
+        })
+    }
+}
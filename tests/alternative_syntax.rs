@@ -0,0 +1,296 @@
+mod harness;
+
+use arma_storage::Value;
+use harness::{call, call_alt, error, ok, ok_with, string};
+use std::{io, slice};
+
+/// Replays `arma_storage_test_alternative_syntax.sqf`
+#[test]
+fn sqf_test_script() {
+    let (_, code) = call("errorCodes");
+    assert_eq!(code, 0);
+
+    let test = [string("test")];
+    assert_eq!(call_alt("open", &test), ok());
+    assert_eq!(
+        call_alt("open", &test),
+        error(26, "storageOpen", "Storage file is open", string(""))
+    );
+    assert_eq!(call_alt("close", &test), ok());
+    assert_eq!(
+        call_alt("close", &test),
+        error(22, "storageNotOpen", "Storage file is not open", string(""))
+    );
+
+    let spam = [string("spam")];
+    for function in ["read", "write"] {
+        assert_eq!(
+            call_alt(function, &spam),
+            error(22, "storageNotOpen", "Storage file is not open", string(""))
+        );
+    }
+    assert_eq!(call_alt("open", &spam), ok());
+
+    assert_eq!(
+        call_alt("read", &spam),
+        error(
+            22,
+            "fileNotFound",
+            &io::Error::from_raw_os_error(2).to_string(),
+            string("")
+        )
+    );
+
+    assert_eq!(call_alt("write", &spam), ok());
+    assert_eq!(call_alt("close", &spam), ok());
+}
+
+#[test]
+fn values_survive_write_and_read() {
+    let storage = string("values");
+    let values = vec![
+        Value::Number(-12.5),
+        Value::Boolean(true),
+        string("with \"quotes\""),
+        Value::Array(vec![
+            Value::Number(1.),
+            Value::Array(vec![string("nested")]),
+        ]),
+    ];
+
+    assert_eq!(call_alt("open", slice::from_ref(&storage)), ok());
+    for (index, value) in values.iter().enumerate() {
+        let key = string(&format!("key{}", index));
+        assert_eq!(
+            call_alt("set", &[storage.clone(), key, value.clone()]),
+            ok()
+        );
+    }
+    assert_eq!(call_alt("write", slice::from_ref(&storage)), ok());
+    assert_eq!(call_alt("close", slice::from_ref(&storage)), ok());
+
+    assert_eq!(call_alt("open", slice::from_ref(&storage)), ok());
+    assert_eq!(call_alt("read", slice::from_ref(&storage)), ok());
+    for (index, value) in values.into_iter().enumerate() {
+        let key = string(&format!("key{}", index));
+        assert_eq!(call_alt("get", &[storage.clone(), key]), ok_with(value));
+    }
+}
+
+#[test]
+fn argument_errors() {
+    assert_eq!(
+        call_alt("", &[]),
+        error(
+            11,
+            "emptyArgument",
+            "Argument function is empty",
+            string("function")
+        )
+    );
+    assert_eq!(
+        call_alt("spam", &[]),
+        error(
+            2,
+            "unknownFunction",
+            "Unknown function spam",
+            string("spam")
+        )
+    );
+    assert_eq!(
+        call_alt("open", &[]),
+        error(
+            10,
+            "missingArgument",
+            "Missing argument name",
+            string("name")
+        )
+    );
+    assert_eq!(
+        call_alt("open", &[string("")]),
+        error(
+            11,
+            "emptyArgument",
+            "Argument name is empty",
+            string("name")
+        )
+    );
+    assert_eq!(
+        call_alt("close", &[string("spam"), string("eggs")]),
+        error(
+            12,
+            "tooManyArguments",
+            "close takes at most 1 arguments but got 2",
+            Value::Number(1.)
+        )
+    );
+    assert_eq!(
+        call_alt(
+            "set",
+            &[
+                string("spam"),
+                string("eggs"),
+                Value::Number(1.),
+                Value::Number(1e30)
+            ]
+        ),
+        error(
+            12,
+            "invalidArgument",
+            "Argument ttl could not be parsed",
+            string("ttl")
+        )
+    );
+}
+
+#[test]
+fn standard_functions_are_available() {
+    let storage = string("alt_functions");
+
+    assert_eq!(call_alt("open", slice::from_ref(&storage)), ok());
+    assert_eq!(
+        call_alt("set", &[storage.clone(), string("rank"), string("Major")]),
+        ok()
+    );
+    assert_eq!(
+        call_alt("exists", &[storage.clone(), string("rank")]),
+        ok_with(Value::Boolean(true))
+    );
+    assert_eq!(call_alt("erase", &[storage.clone(), string("rank")]), ok());
+    assert_eq!(
+        call_alt("exists", &[storage.clone(), string("rank")]),
+        ok_with(Value::Boolean(false))
+    );
+    assert_eq!(call_alt("close", &[storage]), ok());
+}
+
+#[test]
+fn export_and_import() {
+    let values = vec![
+        Value::Number(0.25),
+        Value::Boolean(false),
+        string("line \"one\"\nline two"),
+        Value::Array(vec![]),
+        Value::Array(vec![
+            Value::Number(-1.),
+            Value::Array(vec![Value::Boolean(true)]),
+        ]),
+    ];
+
+    let original = string("exported");
+    assert_eq!(call_alt("open", slice::from_ref(&original)), ok());
+    for (index, value) in values.iter().enumerate() {
+        let key = string(&format!("key{}", index));
+        assert_eq!(
+            call_alt("set", &[original.clone(), key, value.clone()]),
+            ok()
+        );
+    }
+    assert_eq!(
+        call_alt("export", slice::from_ref(&original)),
+        ok_with(string("exported.sqf"))
+    );
+
+    let copy = string("imported");
+    assert_eq!(call_alt("open", slice::from_ref(&copy)), ok());
+    assert_eq!(
+        call_alt("import", &[copy.clone(), string("exported.sqf")]),
+        ok()
+    );
+    for (index, value) in values.into_iter().enumerate() {
+        let key = string(&format!("key{}", index));
+        assert_eq!(call_alt("get", &[copy.clone(), key]), ok_with(value));
+    }
+
+    let (_, code) = call_alt("import", &[copy, string("../exported.sqf")]);
+    assert_eq!(code, 12);
+
+    // comments are only allowed in imported files
+    let (_, code) = call("set|exported|key|[1 // 2\n]");
+    assert_eq!(code, 12);
+}
+
+#[test]
+fn errors_name_what_failed() {
+    let storage = string("errors");
+    assert_eq!(call_alt("open", slice::from_ref(&storage)), ok());
+    assert_eq!(
+        call_alt(
+            "set",
+            &[storage.clone(), string("money"), Value::Number(100.)]
+        ),
+        ok()
+    );
+
+    assert_eq!(
+        call_alt("get", &[storage.clone(), string("rank")]),
+        error(22, "keyNotFound", "Storage has no key rank", string("rank"))
+    );
+    assert_eq!(
+        call_alt(
+            "removeAt",
+            &[storage.clone(), string("money"), Value::Number(0.)]
+        ),
+        error(
+            28,
+            "typeMismatch",
+            "Value of key money is SCALAR but ARRAY is required",
+            Value::Array(vec![string("money"), string("ARRAY"), string("SCALAR")])
+        )
+    );
+
+    let keys = Value::Array(vec![string("money"), string("rank")]);
+    assert_eq!(
+        call_alt("getMany", &[storage, keys]),
+        ok_with(Value::Array(vec![
+            Value::Array(vec![Value::Number(0.), Value::Number(100.)]),
+            error(22, "keyNotFound", "Storage has no key rank", string("rank")).0,
+        ]))
+    );
+}
+
+#[test]
+fn set_many_sets_all_keys_or_none() {
+    let storage = string("batch");
+    let pair = |key: &str, value: f32| Value::Array(vec![string(key), Value::Number(value)]);
+
+    assert_eq!(call_alt("open", slice::from_ref(&storage)), ok());
+
+    let (err, code) = call_alt(
+        "setMany",
+        &[
+            storage.clone(),
+            Value::Array(vec![pair("a", 1.), Value::Number(2.), pair("", 3.)]),
+        ],
+    );
+    assert_eq!(code, 12);
+    match err {
+        Value::Array(err) => {
+            assert_eq!(err[1], string("invalidEntries"));
+            assert_eq!(
+                err[3].as_sqf(),
+                r#"[[0], [12, "invalidArgument", "Argument entry could not be parsed", "entry"], [11, "emptyArgument", "Argument key is empty", "key"]]"#
+            );
+        }
+        err => panic!("not an error: {:?}", err),
+    }
+    assert_eq!(
+        call_alt("exists", &[storage.clone(), string("a")]),
+        ok_with(Value::Boolean(false))
+    );
+
+    assert_eq!(
+        call_alt(
+            "setMany",
+            &[
+                storage.clone(),
+                Value::Array(vec![pair("a", 1.), pair("b", 2.)])
+            ]
+        ),
+        ok_with(Value::Array(vec![
+            Value::Array(vec![Value::Number(0.)]),
+            Value::Array(vec![Value::Number(0.)]),
+        ]))
+    );
+    assert_eq!(call_alt("close", &[storage]), ok());
+}
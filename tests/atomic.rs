@@ -0,0 +1,114 @@
+mod harness;
+
+use arma_storage::{StorageError, StoragePool, Value};
+use harness::{call_alt, number, ok, ok_with, storage_dir, string};
+use std::slice;
+
+fn array(values: &[f32]) -> Value {
+    Value::Array(values.iter().copied().map(number).collect())
+}
+
+#[test]
+fn numbers() {
+    let pool = StoragePool::new(storage_dir("numbers"));
+    pool.open("spam").unwrap();
+
+    assert_eq!(pool.increment("spam", "kills", 1.).unwrap(), number(1.));
+    assert_eq!(pool.increment("spam", "kills", 2.5).unwrap(), number(3.5));
+    assert_eq!(pool.decrement("spam", "kills", 4.).unwrap(), number(-0.5));
+
+    pool.set("spam", "name", &string("John"), None).unwrap();
+    let err = pool.increment("spam", "name", 1.).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref(),
+        Some(StorageError::TypeMismatch { .. })
+    ));
+    assert_eq!(pool.get("spam", "name").unwrap(), string("John"));
+}
+
+#[test]
+fn arrays() {
+    let pool = StoragePool::new(storage_dir("arrays"));
+    pool.open("spam").unwrap();
+
+    assert_eq!(pool.push("spam", "list", &number(1.)).unwrap(), 1);
+    assert_eq!(pool.push("spam", "list", &number(2.)).unwrap(), 2);
+    assert!(!pool.push_unique("spam", "list", &number(2.)).unwrap());
+    assert!(pool.push_unique("spam", "list", &number(1.5)).unwrap());
+    assert_eq!(pool.push("spam", "list", &number(2.)).unwrap(), 4);
+    assert_eq!(pool.get("spam", "list").unwrap(), array(&[1., 2., 1.5, 2.]));
+
+    assert_eq!(pool.remove_value("spam", "list", &number(2.)).unwrap(), 2);
+    assert_eq!(pool.remove_at("spam", "list", 0).unwrap(), number(1.));
+    assert_eq!(pool.get("spam", "list").unwrap(), array(&[1.5]));
+
+    let err = pool.remove_at("spam", "list", 1).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref(),
+        Some(StorageError::IndexOutOfBounds {
+            index: 1,
+            len: 1,
+            ..
+        })
+    ));
+    assert!(pool.remove_at("spam", "missing", 0).is_err());
+}
+
+#[test]
+fn booleans() {
+    let pool = StoragePool::new(storage_dir("booleans"));
+    pool.open("spam").unwrap();
+
+    assert!(pool.toggle("spam", "flag").is_err());
+    pool.set("spam", "flag", &Value::Boolean(false), None)
+        .unwrap();
+    assert!(pool.toggle("spam", "flag").unwrap());
+    assert!(!pool.toggle("spam", "flag").unwrap());
+}
+
+#[test]
+fn through_the_extension() {
+    let storage = string("atomic");
+
+    assert_eq!(call_alt("open", slice::from_ref(&storage)), ok());
+    assert_eq!(
+        call_alt("increment", &[storage.clone(), string("kills")]),
+        ok_with(number(1.))
+    );
+    assert_eq!(
+        call_alt("push", &[storage.clone(), string("list"), number(7.)]),
+        ok_with(number(1.))
+    );
+    assert_eq!(
+        call_alt("removeAt", &[storage.clone(), string("list"), number(0.)]),
+        ok_with(number(7.))
+    );
+    assert_eq!(
+        call_alt("removeAt", &[storage, string("list"), number(-1.)]).1,
+        12
+    );
+}
+
+#[test]
+fn compare_and_set_tolerates_rounding() {
+    let pool = StoragePool::new(storage_dir("compare"));
+    pool.open("spam").unwrap();
+    pool.set("spam", "money", &array(&[0.1 + 0.2]), None)
+        .unwrap();
+
+    // what Arma sends back after printing 0.3 with six digits
+    assert!(!pool
+        .compare_and_set("spam", "money", &array(&[0.31]), &array(&[1.]))
+        .unwrap());
+    assert!(pool
+        .compare_and_set("spam", "money", &array(&[0.3]), &array(&[1.]))
+        .unwrap());
+    assert_eq!(pool.get("spam", "money").unwrap(), array(&[1.]));
+
+    // only compareAndSet rounds, arrays keep close but different numbers
+    pool.set("spam", "list", &array(&[1.]), None).unwrap();
+    assert!(pool
+        .push_unique("spam", "list", &number(1.000_000_1))
+        .unwrap());
+    assert_eq!(pool.remove_value("spam", "list", &number(1.)).unwrap(), 1);
+}
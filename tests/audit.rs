@@ -0,0 +1,212 @@
+mod harness;
+
+use arma_storage::{Operation, StorageError, StoragePool, Value};
+use harness::{call_alt, new_pool, number, ok, storage_dir, string};
+use std::{env, fs};
+
+#[test]
+fn history_of_a_key() {
+    let path = storage_dir("history");
+    let pool = new_pool().audit().open(&path, "bank");
+
+    pool.set("bank", "money", &number(100.), None).unwrap();
+    pool.with_caller("John", || pool.decrement("bank", "money", 60.))
+        .unwrap();
+    pool.set("bank", "gold", &number(1.), None).unwrap();
+    pool.erase("bank", "money").unwrap();
+
+    let history = pool.history("bank", "money", None).unwrap();
+    let changes: Vec<_> = history
+        .iter()
+        .map(|entry| (entry.old.clone(), entry.new.clone(), entry.caller.clone()))
+        .collect();
+    assert_eq!(
+        changes,
+        vec![
+            (Some(number(40.)), None, None),
+            (
+                Some(number(100.)),
+                Some(number(40.)),
+                Some("John".to_owned())
+            ),
+            (None, Some(number(100.)), None),
+        ]
+    );
+
+    assert_eq!(pool.history("bank", "money", Some(1)).unwrap().len(), 1);
+    assert!(history[0].time.ends_with('Z'));
+}
+
+#[test]
+fn imports_are_logged() {
+    let path = storage_dir("bulk");
+    let pool = new_pool().audit().open(&path, "bank");
+
+    pool.set("bank", "a", &number(1.), None).unwrap();
+    pool.import_text("bank", r#"[["b", 2]]"#).unwrap();
+
+    pool.set("bank", "b", &Value::String("two\nlines".to_owned()), None)
+        .unwrap();
+
+    let a = pool.history("bank", "a", None).unwrap();
+    assert_eq!(
+        (a[0].old.clone(), a[0].new.clone()),
+        (Some(number(1.)), None)
+    );
+
+    let b = pool.history("bank", "b", None).unwrap();
+    assert_eq!(b.len(), 2);
+    assert_eq!(b[0].new, Some(Value::String("two\nlines".to_owned())));
+
+    let export = path.join("audit.sqf");
+    assert_eq!(pool.export_audit(&export, Some("bank")).unwrap(), 4);
+    match fs::read_to_string(&export).unwrap().parse().unwrap() {
+        Value::Array(entries) => assert_eq!(entries.len(), 4),
+        _ => panic!("export is not an array"),
+    }
+}
+
+#[test]
+fn disabled_by_default() {
+    let path = storage_dir("disabled");
+    let pool = StoragePool::new(&path);
+    pool.open("bank").unwrap();
+    pool.set("bank", "money", &number(1.), None).unwrap();
+
+    assert!(matches!(
+        pool.history("bank", "money", None)
+            .unwrap_err()
+            .downcast_ref(),
+        Some(StorageError::AuditDisabled)
+    ));
+    assert!(!path.join("audit").exists());
+}
+
+#[test]
+fn caller_tag_from_sqf() {
+    env::set_var("ARMA_STORAGE_AUDIT", "true");
+
+    assert_eq!(call_alt("open", &[string("bank")]), ok());
+    assert_eq!(
+        call_alt(
+            "as",
+            &[
+                string("John"),
+                string("set"),
+                string("bank"),
+                string("money"),
+                number(5.),
+            ]
+        ),
+        ok()
+    );
+
+    let (history, code) = call_alt("history", &[string("bank"), string("money"), number(1.)]);
+    assert_eq!(code, 0);
+    match history {
+        Value::Array(history) => match &history[0] {
+            Value::Array(entry) => {
+                assert_eq!(entry[3], Value::Array(vec![]));
+                assert_eq!(entry[4], Value::Array(vec![number(5.)]));
+                assert_eq!(entry[5], string("John"));
+            }
+            _ => panic!("entry is not an array"),
+        },
+        _ => panic!("history is not an array"),
+    }
+}
+
+#[test]
+fn cut_off_entries_are_skipped() {
+    let path = storage_dir("cut_off");
+    let pool = new_pool().audit().open(&path, "bank");
+    pool.set("bank", "money", &number(1.), None).unwrap();
+    drop(pool);
+
+    // a crash while appending
+    let log = path.join("audit").join("changes.log");
+    let mut text = fs::read_to_string(&log).unwrap();
+    text.push_str(r#"["2026-10-19T12:34:56.789Z", "bank", "mon"#);
+    fs::write(&log, text).unwrap();
+
+    let pool = new_pool().audit().open(&path, "bank");
+    pool.set("bank", "money", &number(2.), None).unwrap();
+    pool.set(
+        "bank",
+        "note",
+        &Value::String("two\nlines".to_owned()),
+        None,
+    )
+    .unwrap();
+
+    let money: Vec<_> = pool
+        .history("bank", "money", None)
+        .unwrap()
+        .into_iter()
+        .map(|entry| (entry.old, entry.new))
+        .collect();
+    assert_eq!(
+        money,
+        vec![(None, Some(number(2.))), (None, Some(number(1.)))]
+    );
+    assert_eq!(pool.history("bank", "note", None).unwrap().len(), 1);
+}
+
+#[test]
+fn history_reads_what_was_appended_since() {
+    let path = storage_dir("appended");
+    let pool = new_pool().audit().open(&path, "bank");
+    let other = new_pool().audit().build(&path);
+    let money = |pool: &StoragePool, n| -> Vec<_> {
+        pool.history("bank", "money", n)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.new)
+            .collect()
+    };
+
+    pool.set("bank", "money", &number(1.), None).unwrap();
+    pool.set(
+        "bank",
+        "note",
+        &Value::String("a\n[\"b\"]".to_owned()),
+        None,
+    )
+    .unwrap();
+    assert_eq!(money(&other, None), vec![Some(number(1.))]);
+
+    pool.set("bank", "money", &number(2.), None).unwrap();
+    pool.erase("bank", "money").unwrap();
+    assert_eq!(money(&other, Some(2)), vec![None, Some(number(2.))]);
+    assert_eq!(
+        other.history("bank", "note", None).unwrap()[0].new,
+        Some(Value::String("a\n[\"b\"]".to_owned()))
+    );
+
+    // a new log after the old one was moved away
+    drop(pool);
+    let log = path.join("audit").join("changes.log");
+    fs::remove_file(&log).unwrap();
+    let pool = new_pool().audit().open(&path, "bank");
+    pool.set("bank", "money", &number(3.), None).unwrap();
+    assert_eq!(money(&other, None), vec![Some(number(3.))]);
+}
+
+#[test]
+fn commits_log_the_change_of_the_file() {
+    let path = storage_dir("commit");
+    let pool = new_pool().audit().open(&path, "bank");
+    pool.set("bank", "money", &number(1.), None).unwrap();
+    pool.write("bank").unwrap();
+    // never written
+    pool.set("bank", "money", &number(5.), None).unwrap();
+
+    let id = pool.begin("bank").unwrap();
+    pool.stage(id, Operation::Set("money".to_owned(), number(10.)))
+        .unwrap();
+    pool.commit(id).unwrap();
+
+    let latest = pool.history("bank", "money", Some(1)).unwrap();
+    assert_eq!(latest[0].old, Some(number(1.)));
+    assert_eq!(latest[0].new, Some(number(10.)));
+}
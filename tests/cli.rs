@@ -0,0 +1,55 @@
+mod harness;
+
+use harness::storage_dir;
+use std::{
+    env, fs,
+    path::Path,
+    process::{Command, Output},
+};
+
+fn cli(path: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_arma-storage-cli"))
+        .arg("--path")
+        .arg(path)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: Output) -> String {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn edit_export_and_import() {
+    let path = storage_dir("storages");
+    let export = storage_dir("export").join("players.sqf");
+
+    stdout(cli(&path, &["set", "players", "money", "100"]));
+    stdout(cli(&path, &["set", "players", "name", r#""John""#]));
+    assert_eq!(stdout(cli(&path, &["get", "players", "money"])), "100\n");
+    assert_eq!(stdout(cli(&path, &["list"])), "players\n");
+
+    stdout(cli(&path, &["export", "players", export.to_str().unwrap()]));
+    stdout(cli(&path, &["erase", "players", "money"]));
+    assert_eq!(
+        stdout(cli(&path, &["dump", "players"])),
+        "name = \"John\"\n"
+    );
+
+    stdout(cli(&path, &["import", "copy", export.to_str().unwrap()]));
+    assert_eq!(
+        stdout(cli(&path, &["dump", "copy"])),
+        "money = 100\nname = \"John\"\n"
+    );
+
+    assert_eq!(stdout(cli(&path, &["verify"])), "copy: ok\nplayers: ok\n");
+
+    fs::write(path.join("broken"), b"not a storage").unwrap();
+    assert!(!cli(&path, &["verify", "broken"]).status.success());
+}
@@ -0,0 +1,88 @@
+mod harness;
+
+use arma_storage::{Compression, StorageError, StoragePool, Value};
+use harness::storage_dir;
+use std::{fs, path::Path};
+
+/// Write a storage with repetitive data and return the file size
+fn write(path: &Path, compression: Option<Compression>) -> u64 {
+    let pool = StoragePool::new(path);
+    pool.open("spam").unwrap();
+    pool.set_storage_compression("spam", compression).unwrap();
+
+    for i in 0..100 {
+        let value = Value::String("arifle_MX_F".repeat(10));
+        pool.set("spam", &format!("weapon_{}", i), &value, None)
+            .unwrap();
+    }
+
+    pool.write("spam").unwrap();
+
+    fs::metadata(path.join("spam")).unwrap().len()
+}
+
+fn read(path: &Path) -> Value {
+    let pool = StoragePool::new(path);
+    pool.open("spam").unwrap();
+    pool.read("spam").unwrap();
+
+    pool.get("spam", "weapon_42").unwrap()
+}
+
+/// The compression is the byte after the magic and the version
+fn compression_byte(path: &Path) -> u8 {
+    fs::read(path.join("spam")).unwrap()[5]
+}
+
+#[test]
+fn compressed_files_are_read_transparently() {
+    let path = storage_dir("round_trip");
+    let plain = write(&path, None);
+    let expected = read(&path);
+
+    for compression in [Compression::Zstd, Compression::Lz4] {
+        assert!(write(&path, Some(compression)) < plain);
+        assert_eq!(read(&path), expected);
+    }
+}
+
+#[test]
+fn compression_is_kept_on_rewrite() {
+    let path = storage_dir("rewrite");
+    write(&path, Some(Compression::Zstd));
+
+    // like the command line tool changing a key
+    let pool = StoragePool::new(&path);
+    pool.open("spam").unwrap();
+    pool.read("spam").unwrap();
+    pool.write("spam").unwrap();
+
+    assert_eq!(compression_byte(&path), 1);
+    drop(pool);
+
+    // the compression of the pool replaces the one of the file
+    let mut pool = StoragePool::new(&path);
+    pool.set_compression(Compression::None);
+    pool.open("spam").unwrap();
+    pool.read("spam").unwrap();
+    pool.write("spam").unwrap();
+
+    assert_eq!(compression_byte(&path), 0);
+}
+
+#[test]
+fn unknown_compression_is_rejected() {
+    let path = storage_dir("unknown");
+    write(&path, Some(Compression::Lz4));
+
+    let mut bytes = fs::read(path.join("spam")).unwrap();
+    bytes[5] = 99;
+    fs::write(path.join("spam"), bytes).unwrap();
+
+    let pool = StoragePool::new(&path);
+    pool.open("spam").unwrap();
+    assert!(matches!(
+        pool.read("spam").unwrap_err().downcast_ref(),
+        Some(StorageError::UnsupportedCompression(99))
+    ));
+}
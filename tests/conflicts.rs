@@ -0,0 +1,136 @@
+mod harness;
+
+use arma_storage::{
+    ConflictMode, LockMode, RVExtensionRegisterCallback, StorageError, StoragePool, Value,
+};
+use harness::{call_alt, extension_dir, ok, storage_dir, string};
+use std::{
+    env,
+    ffi::CStr,
+    os::raw::{c_char, c_int},
+    path::PathBuf,
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+/// Change a storage file like an admin tool would. Waits first so the
+/// modification time differs on file systems with coarse timestamps.
+fn edit(path: &PathBuf, key: &str, value: Value) {
+    thread::sleep(Duration::from_millis(50));
+
+    let admin = StoragePool::new(path);
+    admin.open_with("spam", LockMode::Unlocked).unwrap();
+    admin.read("spam").unwrap();
+    admin.set("spam", key, &value, None).unwrap();
+    admin.write("spam").unwrap();
+}
+
+#[test]
+fn refuses_to_overwrite_changes_on_disk() {
+    let path = storage_dir("refuse");
+    let server = StoragePool::new(&path);
+
+    server.open("spam").unwrap();
+    server
+        .set("spam", "money", &Value::Number(100.), None)
+        .unwrap();
+    server.write("spam").unwrap();
+
+    edit(&path, "money", Value::Number(5000.));
+
+    server
+        .set("spam", "rank", &Value::Number(1.), None)
+        .unwrap();
+    assert!(matches!(
+        server.write("spam").unwrap_err().downcast_ref(),
+        Some(StorageError::ModifiedOnDisk(_))
+    ));
+
+    // the change on disk survived and reading makes the storage writable again
+    server.read("spam").unwrap();
+    assert_eq!(server.get("spam", "money").unwrap(), Value::Number(5000.));
+    server.write("spam").unwrap();
+}
+
+#[test]
+fn warn_mode_overwrites_changes_on_disk() {
+    let path = storage_dir("warn");
+    let mut server = StoragePool::new(&path);
+    server.set_conflict_mode(ConflictMode::Warn);
+
+    server.open("spam").unwrap();
+    server.write("spam").unwrap();
+
+    edit(&path, "money", Value::Number(5000.));
+
+    server.write("spam").unwrap();
+    server.read("spam").unwrap();
+    assert!(!server.exists("spam", "money").unwrap());
+}
+
+#[test]
+fn reloads_changed_storages() {
+    let path = storage_dir("reload");
+    let server = StoragePool::new(&path);
+
+    server.open("spam").unwrap();
+    server.write("spam").unwrap();
+    server.open("eggs").unwrap();
+    server.write("eggs").unwrap();
+
+    assert!(server.reload_changed().is_empty());
+
+    edit(&path, "money", Value::Number(5000.));
+
+    assert_eq!(server.reload_changed(), vec!["spam".to_owned()]);
+    assert_eq!(server.get("spam", "money").unwrap(), Value::Number(5000.));
+    assert!(server.reload_changed().is_empty());
+}
+
+static CALLBACKS: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+extern "system" fn callback(
+    _name: *const c_char,
+    function: *const c_char,
+    data: *const c_char,
+) -> c_int {
+    let (function, data) = unsafe { (CStr::from_ptr(function), CStr::from_ptr(data)) };
+
+    CALLBACKS.lock().unwrap().push((
+        function.to_string_lossy().into_owned(),
+        data.to_string_lossy().into_owned(),
+    ));
+
+    0
+}
+
+#[test]
+fn watcher_raises_callback() {
+    env::set_var("ARMA_STORAGE_WATCH", "0.1");
+    RVExtensionRegisterCallback(callback);
+
+    let spam = [string("spam")];
+    assert_eq!(call_alt("open", &spam), ok());
+    assert_eq!(call_alt("write", &spam), ok());
+
+    let path = extension_dir();
+    edit(&path, "money", Value::Number(5000.));
+
+    for _ in 0..50 {
+        if !CALLBACKS.lock().unwrap().is_empty() {
+            break;
+        }
+
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    assert_eq!(
+        CALLBACKS.lock().unwrap().as_slice(),
+        &[("storageChanged".to_owned(), "spam".to_owned())]
+    );
+    assert_eq!(
+        call_alt("get", &[string("spam"), string("money")]),
+        (Value::Number(5000.), 0)
+    );
+}
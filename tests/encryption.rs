@@ -0,0 +1,111 @@
+mod harness;
+
+use arma_storage::{EncryptionKey, StorageError, Value};
+use harness::{new_pool, storage_dir};
+use std::{fs, path::Path};
+
+fn key(digit: char) -> EncryptionKey {
+    digit.to_string().repeat(64).parse().unwrap()
+}
+
+fn write(path: &Path, key: Option<EncryptionKey>) {
+    let pool = new_pool().key(key).open(path, "spam");
+    pool.set("spam", "secret", &Value::String("hunter2".to_owned()), None)
+        .unwrap();
+    pool.write("spam").unwrap();
+}
+
+fn read_error(path: &Path, key: Option<EncryptionKey>) -> anyhow::Error {
+    new_pool()
+        .key(key)
+        .open(path, "spam")
+        .read("spam")
+        .unwrap_err()
+}
+
+#[test]
+fn encrypted_files_are_read_with_the_key() {
+    let path = storage_dir("round_trip");
+    write(&path, Some(key('a')));
+
+    let bytes = fs::read(path.join("spam")).unwrap();
+    assert!(!bytes.windows(7).any(|window| window == b"hunter2"));
+
+    let pool = new_pool().key(Some(key('a'))).open(&path, "spam");
+    pool.read("spam").unwrap();
+    assert_eq!(
+        pool.get("spam", "secret").unwrap(),
+        Value::String("hunter2".to_owned())
+    );
+}
+
+#[test]
+fn missing_or_wrong_key_is_rejected() {
+    let path = storage_dir("wrong_key");
+    write(&path, Some(key('a')));
+
+    assert!(matches!(
+        read_error(&path, None).downcast_ref(),
+        Some(StorageError::KeyRequired)
+    ));
+    assert!(matches!(
+        read_error(&path, Some(key('b'))).downcast_ref(),
+        Some(StorageError::WrongKey)
+    ));
+}
+
+#[test]
+fn changed_files_are_rejected() {
+    let path = storage_dir("tampered");
+    write(&path, Some(key('a')));
+    let bytes = fs::read(path.join("spam")).unwrap();
+
+    // the compression byte in the header and the last byte of the data, the
+    // checksum notices before decrypting
+    for index in [5, bytes.len() - 1] {
+        let mut tampered = bytes.clone();
+        tampered[index] ^= 1;
+        fs::write(path.join("spam"), tampered).unwrap();
+
+        assert!(matches!(
+            read_error(&path, Some(key('a'))).downcast_ref(),
+            Some(StorageError::ChecksumMismatch)
+        ));
+    }
+}
+
+#[test]
+fn encrypted_files_belong_to_their_storage() {
+    let path = storage_dir("moved");
+    write(&path, Some(key('a')));
+    fs::copy(path.join("spam"), path.join("eggs")).unwrap();
+
+    let pool = new_pool().key(Some(key('a'))).open(&path, "eggs");
+    assert!(matches!(
+        pool.read("eggs").unwrap_err().downcast_ref(),
+        Some(StorageError::WrongKey)
+    ));
+}
+
+#[test]
+fn unencrypted_files_are_migrated() {
+    let path = storage_dir("migrate");
+    write(&path, None);
+
+    let pool = new_pool().key(Some(key('a'))).open(&path, "spam");
+    pool.read("spam").unwrap();
+    pool.write("spam").unwrap();
+    drop(pool);
+
+    assert!(matches!(
+        read_error(&path, None).downcast_ref(),
+        Some(StorageError::KeyRequired)
+    ));
+}
+
+#[test]
+fn invalid_keys_are_rejected() {
+    assert!("abc".parse::<EncryptionKey>().is_err());
+    assert!("g".repeat(64).parse::<EncryptionKey>().is_err());
+    assert_eq!(format!("{:?}", key('a')), "EncryptionKey(..)");
+}
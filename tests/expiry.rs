@@ -0,0 +1,130 @@
+mod harness;
+
+use arma_storage::{KeyFilter, StorageError, Value};
+use harness::{call_alt, new_pool, number, ok, ok_with, storage_dir, string};
+use std::{slice, thread, time::Duration};
+
+const SHORT: Duration = Duration::from_millis(20);
+
+fn is_missing(result: anyhow::Result<impl std::fmt::Debug>) -> bool {
+    matches!(
+        result.unwrap_err().downcast_ref(),
+        Some(StorageError::StorageMissingKey(_))
+    )
+}
+
+#[test]
+fn expired_keys_disappear() {
+    let pool = new_pool().open(storage_dir("disappear"), "spam");
+    pool.set("spam", "short", &number(1.), Some(SHORT)).unwrap();
+    pool.set("spam", "long", &number(2.), Some(Duration::from_secs(3600)))
+        .unwrap();
+    pool.set("spam", "forever", &number(3.), None).unwrap();
+
+    assert_eq!(pool.get("spam", "short").unwrap(), number(1.));
+    thread::sleep(SHORT * 2);
+
+    assert!(is_missing(pool.get("spam", "short")));
+    assert!(is_missing(pool.ttl("spam", "short")));
+    assert!(!pool.exists("spam", "short").unwrap());
+    assert_eq!(
+        pool.keys("spam", &KeyFilter::All, 0, None).unwrap(),
+        vec!["forever".to_owned(), "long".to_owned()]
+    );
+    assert_eq!(pool.get("spam", "long").unwrap(), number(2.));
+}
+
+#[test]
+fn sweep_removes_expired_keys() {
+    let pool = new_pool().open(storage_dir("sweep"), "spam");
+    pool.set("spam", "forever", &number(1.), None).unwrap();
+    let before = pool.stats("spam").unwrap();
+
+    pool.set(
+        "spam",
+        "short",
+        &Value::String("x".repeat(100)),
+        Some(SHORT),
+    )
+    .unwrap();
+    assert!(pool.stats("spam").unwrap().size > before.size);
+    // nothing has expired yet
+    assert_eq!(pool.sweep(), 0);
+
+    thread::sleep(SHORT * 2);
+    assert_eq!(pool.sweep(), 1);
+
+    let after = pool.stats("spam").unwrap();
+    assert_eq!(after.keys, 1);
+    assert_eq!(after.size, before.size);
+    assert_eq!(after.total_size, before.total_size);
+    assert_eq!(pool.sweep(), 0);
+}
+
+#[test]
+fn ttl_is_the_remaining_time() {
+    let pool = new_pool().open(storage_dir("ttl"), "spam");
+    pool.set("spam", "hour", &number(1.), Some(Duration::from_secs(3600)))
+        .unwrap();
+    pool.set("spam", "forever", &number(2.), None).unwrap();
+
+    let ttl = pool.ttl("spam", "hour").unwrap().unwrap();
+    assert!(ttl <= Duration::from_secs(3600) && ttl > Duration::from_secs(3590));
+    assert_eq!(pool.ttl("spam", "forever").unwrap(), None);
+
+    // setting a key again without ttl keeps it forever
+    pool.set("spam", "hour", &number(3.), None).unwrap();
+    assert_eq!(pool.ttl("spam", "hour").unwrap(), None);
+}
+
+#[test]
+fn ttl_through_the_extension() {
+    let storage = string("ttl");
+
+    assert_eq!(call_alt("open", slice::from_ref(&storage)), ok());
+    assert_eq!(
+        call_alt(
+            "set",
+            &[storage.clone(), string("hour"), number(1.), number(3600.)]
+        ),
+        ok()
+    );
+    assert_eq!(
+        call_alt("set", &[storage.clone(), string("forever"), number(2.)]),
+        ok()
+    );
+
+    match call_alt("ttl", &[storage.clone(), string("hour")]) {
+        (Value::Number(ttl), 0) => assert!(ttl > 3590. && ttl <= 3600.),
+        response => panic!("unexpected response {:?}", response),
+    }
+    assert_eq!(
+        call_alt("ttl", &[storage.clone(), string("forever")]),
+        ok_with(number(-1.))
+    );
+    assert_eq!(call_alt("close", &[storage]), ok());
+}
+
+#[test]
+fn expiry_survives_write_and_read() {
+    let path = storage_dir("survive");
+
+    let pool = new_pool().open(&path, "spam");
+    pool.set("spam", "short", &number(1.), Some(SHORT * 10))
+        .unwrap();
+    pool.set("spam", "hour", &number(2.), Some(Duration::from_secs(3600)))
+        .unwrap();
+    pool.set("spam", "forever", &number(3.), None).unwrap();
+    pool.write("spam").unwrap();
+    drop(pool);
+
+    let pool = new_pool().open(&path, "spam");
+    pool.read("spam").unwrap();
+    assert!(pool.ttl("spam", "short").unwrap().unwrap() <= SHORT * 10);
+    assert!(pool.ttl("spam", "hour").unwrap().unwrap() > Duration::from_secs(3590));
+    assert_eq!(pool.ttl("spam", "forever").unwrap(), None);
+
+    thread::sleep(SHORT * 11);
+    assert!(is_missing(pool.get("spam", "short")));
+    assert_eq!(pool.get("spam", "hour").unwrap(), number(2.));
+}
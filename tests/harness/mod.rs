@@ -0,0 +1,195 @@
+//! Calls the extension the way Arma does: through the exported functions with
+//! a C buffer for the response and every argument stringified.
+#![allow(dead_code)]
+
+use arma_storage::{
+    EncryptionKey, Limits, RVExtension, RVExtensionArgs, RVExtensionVersion, StoragePool, Value,
+};
+use std::{
+    env,
+    ffi::{CStr, CString},
+    fs,
+    os::raw::{c_char, c_int},
+    path::{Path, PathBuf},
+    ptr,
+    sync::Once,
+};
+
+/// Size of the response buffer Arma passes to the extension
+pub const OUTPUT_SIZE: usize = 20480;
+
+/// Use a fresh storage directory for every test binary
+pub fn setup() {
+    static SETUP: Once = Once::new();
+
+    SETUP.call_once(|| {
+        let path = extension_dir();
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        env::set_var("ARMA_STORAGE_PATH", path);
+
+        let mut output = [0 as c_char; OUTPUT_SIZE];
+        unsafe { RVExtensionVersion(output.as_mut_ptr(), OUTPUT_SIZE as c_int) };
+    });
+}
+
+/// Storage directory of the extension in this test binary
+pub fn extension_dir() -> PathBuf {
+    env::temp_dir().join(format!("arma_storage_{}", env!("CARGO_CRATE_NAME")))
+}
+
+/// Fresh directory for a storage pool of a single test
+pub fn storage_dir(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!(
+        "arma_storage_{}_{}",
+        env!("CARGO_CRATE_NAME"),
+        name
+    ));
+    let _ = fs::remove_dir_all(&path);
+    fs::create_dir_all(&path).unwrap();
+    path
+}
+
+/// Options of a storage pool used without the extension, e.g.
+/// `new_pool().journal().open(&path, "spam")`
+#[derive(Default)]
+pub struct PoolBuilder {
+    journal: bool,
+    audit: bool,
+    key: Option<EncryptionKey>,
+    limits: Limits,
+}
+
+pub fn new_pool() -> PoolBuilder {
+    PoolBuilder::default()
+}
+
+impl PoolBuilder {
+    pub fn journal(mut self) -> Self {
+        self.journal = true;
+        self
+    }
+
+    pub fn audit(mut self) -> Self {
+        self.audit = true;
+        self
+    }
+
+    pub fn key(mut self, key: Option<EncryptionKey>) -> Self {
+        self.key = key;
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn build<P: AsRef<Path>>(self, path: P) -> StoragePool {
+        let mut pool = StoragePool::new(path.as_ref());
+        pool.set_journal(self.journal);
+        pool.set_audit(self.audit);
+        pool.set_key(self.key);
+        pool.set_limits(self.limits);
+        pool
+    }
+
+    /// Build the pool and open a storage in it
+    pub fn open<P: AsRef<Path>>(self, path: P, storage: &str) -> StoragePool {
+        let pool = self.build(path);
+        pool.open(storage).unwrap();
+        pool
+    }
+}
+
+/// Parse the response buffer like `parseSimpleArray` would
+fn response(output: &[c_char]) -> Value {
+    let output = unsafe { CStr::from_ptr(output.as_ptr()) }.to_str().unwrap();
+
+    if output.is_empty() {
+        Value::Void
+    } else {
+        output
+            .parse()
+            .unwrap_or_else(|err| panic!("invalid response {:?}: {}", output, err))
+    }
+}
+
+/// `"arma_storage" callExtension input`
+pub fn call(input: &str) -> (Value, c_int) {
+    setup();
+
+    let mut output = [0 as c_char; OUTPUT_SIZE];
+    let input = CString::new(input).unwrap();
+
+    let code = unsafe { RVExtension(output.as_mut_ptr(), OUTPUT_SIZE as c_int, input.as_ptr()) };
+
+    (response(&output), code)
+}
+
+/// `"arma_storage" callExtension [function, args]`
+pub fn call_args(function: &str, args: &[Value]) -> (Value, c_int) {
+    setup();
+
+    let mut output = [0 as c_char; OUTPUT_SIZE];
+    let function = CString::new(function).unwrap();
+    // Arma stringifies every argument
+    let args: Vec<CString> = args
+        .iter()
+        .map(|arg| CString::new(arg.as_sqf()).unwrap())
+        .collect();
+    let argv: Vec<*const c_char> = args.iter().map(|arg| arg.as_ptr()).collect();
+
+    let code = unsafe {
+        RVExtensionArgs(
+            output.as_mut_ptr(),
+            OUTPUT_SIZE as c_int,
+            function.as_ptr(),
+            if argv.is_empty() {
+                ptr::null()
+            } else {
+                argv.as_ptr()
+            },
+            argv.len() as c_int,
+        )
+    };
+
+    (response(&output), code)
+}
+
+/// `"arma_storage" callExtension ["", [function, args]]`
+pub fn call_alt(function: &str, args: &[Value]) -> (Value, c_int) {
+    let mut all = vec![string(function)];
+    all.extend_from_slice(args);
+
+    call_args("", &all)
+}
+
+pub fn string(string: &str) -> Value {
+    Value::String(string.to_owned())
+}
+
+pub fn number(number: f32) -> Value {
+    Value::Number(number)
+}
+
+pub fn ok() -> (Value, c_int) {
+    (Value::Void, 0)
+}
+
+pub fn ok_with(value: Value) -> (Value, c_int) {
+    (value, 0)
+}
+
+/// Structured error `[code, identifier, message, context]`
+pub fn error(code: c_int, identifier: &str, message: &str, context: Value) -> (Value, c_int) {
+    (
+        Value::Array(vec![
+            Value::Number(code as f32),
+            string(identifier),
+            string(message),
+            context,
+        ]),
+        code,
+    )
+}
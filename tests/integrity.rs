@@ -0,0 +1,113 @@
+mod harness;
+
+use arma_storage::{StorageError, StoragePool, Value, Verification};
+use harness::storage_dir;
+use std::{fs, path::Path, time::Duration};
+
+fn value(i: usize) -> Value {
+    Value::String(format!("value_{}", i))
+}
+
+/// Write a storage with 50 keys and one key with a time to live
+fn write(path: &Path) -> StoragePool {
+    let pool = StoragePool::new(path);
+    pool.open("spam").unwrap();
+
+    for i in 0..50 {
+        pool.set("spam", &format!("key_{}", i), &value(i), None)
+            .unwrap();
+    }
+    pool.set(
+        "spam",
+        "expiring",
+        &Value::Boolean(true),
+        Some(Duration::from_secs(3600)),
+    )
+    .unwrap();
+
+    pool.write("spam").unwrap();
+    pool
+}
+
+/// Change the bytes of the file at the first occurrence of `needle` plus
+/// `offset`
+fn damage(path: &Path, needle: &[u8], offset: isize, replacement: &[u8]) {
+    let file = path.join("spam");
+    let mut bytes = fs::read(&file).unwrap();
+    let position = bytes
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .unwrap();
+    let start = (position as isize + offset) as usize;
+
+    bytes[start..start + replacement.len()].copy_from_slice(replacement);
+    fs::write(&file, bytes).unwrap();
+}
+
+#[test]
+fn verify_reports_damage() {
+    let path = storage_dir("verify");
+    let pool = write(&path);
+
+    assert_eq!(
+        pool.verify("spam").unwrap(),
+        Verification::Intact { keys: 51 }
+    );
+
+    // a changed value still decodes, only the checksum notices
+    damage(&path, b"value_7", 0, b"VALUE");
+
+    assert!(matches!(
+        pool.verify("spam").unwrap(),
+        Verification::Damaged(reason) if reason.contains("checksum")
+    ));
+    assert!(matches!(
+        pool.read("spam").unwrap_err().downcast_ref(),
+        Some(StorageError::ChecksumMismatch)
+    ));
+
+    fs::write(path.join("spam"), b"ARST\x04\x00").unwrap();
+    assert!(matches!(
+        pool.read("spam").unwrap_err().downcast_ref(),
+        Some(StorageError::Truncated)
+    ));
+}
+
+#[test]
+fn salvage_recovers_intact_keys() {
+    let path = storage_dir("salvage");
+    drop(write(&path));
+
+    // the length of key_25 no longer fits into the file
+    damage(&path, b"key_25", -8, &[0xff; 8]);
+
+    let pool = StoragePool::new(&path);
+    pool.open("spam").unwrap();
+    assert!(pool.read("spam").is_err());
+
+    assert_eq!(pool.salvage("spam").unwrap(), 50);
+    assert!(!pool.exists("spam", "key_25").unwrap());
+    for i in (0..50).filter(|&i| i != 25) {
+        assert_eq!(pool.get("spam", &format!("key_{}", i)).unwrap(), value(i));
+    }
+    assert!(pool.ttl("spam", "expiring").unwrap().is_some());
+
+    // the damaged file is kept but not listed as a storage
+    assert!(path.join("spam.damaged").exists());
+    assert_eq!(pool.files_on_disk().unwrap(), vec!["spam".to_owned()]);
+
+    pool.write("spam").unwrap();
+    assert_eq!(
+        pool.verify("spam").unwrap(),
+        Verification::Intact { keys: 50 }
+    );
+}
+
+#[test]
+fn salvage_of_intact_file_reads_everything() {
+    let path = storage_dir("intact");
+    let pool = write(&path);
+
+    assert_eq!(pool.salvage("spam").unwrap(), 51);
+    assert_eq!(pool.get("spam", "key_42").unwrap(), value(42));
+}
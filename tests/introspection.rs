@@ -0,0 +1,137 @@
+mod harness;
+
+use arma_storage::{LockMode, StoragePool, Value};
+use harness::{call_alt, ok, ok_with, storage_dir, string};
+use std::{env, slice};
+
+/// Field of an open storage as `openStorages` returns it
+fn open_storage(name: &Value) -> impl Fn(&str) -> Value {
+    let storages = match call_alt("openStorages", &[]) {
+        (Value::Array(storages), 0) => storages,
+        response => panic!("not an array: {:?}", response),
+    };
+    let fields = storages
+        .into_iter()
+        .find_map(|storage| match storage {
+            Value::Array(fields)
+                if fields[0] == Value::Array(vec![string("name"), name.clone()]) =>
+            {
+                Some(fields)
+            }
+            _ => None,
+        })
+        .expect("storage is not open");
+
+    move |field| {
+        fields
+            .iter()
+            .find_map(|pair| match pair {
+                Value::Array(pair) if pair[0] == string(field) => Some(pair[1].clone()),
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("no field {}", field))
+    }
+}
+
+#[test]
+fn storage_info_tracks_unwritten_changes() {
+    let pool = StoragePool::new(storage_dir("dirty"));
+    pool.open("spam").unwrap();
+
+    let info = &pool.storage_info()[0];
+    assert_eq!(info.name, "spam");
+    assert_eq!(info.lock_mode, LockMode::Exclusive);
+    assert!(!info.dirty);
+    assert_eq!((info.last_read, info.last_write), (None, None));
+
+    pool.set("spam", "rank", &string("Major"), None).unwrap();
+    let info = &pool.storage_info()[0];
+    assert!(info.dirty);
+    assert_eq!(info.keys, 1);
+    assert_eq!(info.size, "rank".len() + string("Major").size());
+    assert!(info.memory > info.size);
+
+    pool.write("spam").unwrap();
+    let info = &pool.storage_info()[0];
+    assert!(!info.dirty);
+    assert!(info.last_write.is_some());
+
+    pool.erase("spam", "rank").unwrap();
+    assert!(pool.storage_info()[0].dirty);
+
+    pool.read("spam").unwrap();
+    let info = &pool.storage_info()[0];
+    assert!(!info.dirty);
+    assert_eq!(info.keys, 1);
+    assert!(info.last_read >= info.last_write);
+}
+
+#[test]
+fn closed_files_on_disk() {
+    let pool = StoragePool::new(storage_dir("closed"));
+
+    for name in ["eggs", "ham", "spam"] {
+        pool.open(name).unwrap();
+        pool.write(name).unwrap();
+    }
+    pool.close("eggs").unwrap();
+    pool.close("spam").unwrap();
+
+    assert_eq!(pool.closed_files_on_disk().unwrap(), vec!["eggs", "spam"]);
+}
+
+#[test]
+fn info_commands() {
+    let (info, code) = call_alt("info", &[]);
+    assert_eq!(code, 0);
+    let info = match info {
+        Value::Array(info) => info,
+        info => panic!("not an array: {:?}", info),
+    };
+    assert_eq!(
+        info[0],
+        Value::Array(vec![string("version"), string(env!("CARGO_PKG_VERSION"))])
+    );
+    assert_eq!(
+        info[3],
+        Value::Array(vec![string("lockMode"), string("exclusive")])
+    );
+
+    let storage = string("introspected");
+    assert_eq!(call_alt("open", slice::from_ref(&storage)), ok());
+    assert_eq!(
+        call_alt("set", &[storage.clone(), string("rank"), string("Major")]),
+        ok()
+    );
+
+    let storage_info = open_storage(&storage);
+    assert_eq!(storage_info("keys"), Value::Number(1.));
+    assert_eq!(
+        storage_info("size"),
+        Value::Number(("rank".len() + string("Major").size()) as f32)
+    );
+    assert!(matches!(storage_info("memory"), Value::Number(memory) if memory > 0.));
+    assert_eq!(storage_info("dirty"), Value::Boolean(true));
+    assert_eq!(storage_info("lockMode"), string("exclusive"));
+    assert_eq!(storage_info("lastWrite"), string(""));
+
+    // a commit writes the storage
+    let (id, code) = call_alt("begin", slice::from_ref(&storage));
+    assert_eq!(code, 0);
+    assert_eq!(
+        call_alt("txSet", &[id.clone(), string("rank"), string("Colonel")]),
+        ok()
+    );
+    assert_eq!(call_alt("commit", &[id]), ok());
+    let storage_info = open_storage(&storage);
+    assert_eq!(storage_info("dirty"), Value::Boolean(false));
+    assert!(matches!(storage_info("lastWrite"), Value::String(time) if !time.is_empty()));
+
+    assert_eq!(call_alt("write", slice::from_ref(&storage)), ok());
+    assert_eq!(call_alt("diskStorages", &[]), ok_with(Value::Array(vec![])));
+    assert_eq!(call_alt("close", slice::from_ref(&storage)), ok());
+    assert_eq!(
+        call_alt("diskStorages", &[]),
+        ok_with(Value::Array(vec![storage]))
+    );
+}
@@ -0,0 +1,186 @@
+mod harness;
+
+use arma_storage::{EncryptionKey, StorageError, Value};
+use harness::{new_pool, number, storage_dir};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    time::Duration,
+};
+
+#[test]
+fn changes_survive_without_write() {
+    let path = storage_dir("survive");
+
+    let pool = new_pool().journal().open(&path, "spam");
+    pool.set("spam", "a", &number(1.), None).unwrap();
+    pool.write("spam").unwrap();
+    assert!(!path.join("spam.journal").exists());
+
+    pool.set("spam", "b", &number(2.), Some(Duration::from_secs(3600)))
+        .unwrap();
+    pool.increment("spam", "a", 10.).unwrap();
+    pool.push("spam", "c", &number(3.)).unwrap();
+    pool.set_many("spam", &[("d".to_owned(), number(4.))])
+        .unwrap();
+    pool.erase("spam", "d").unwrap();
+    // crash without writing
+    drop(pool);
+
+    let pool = new_pool().journal().open(&path, "spam");
+    pool.read("spam").unwrap();
+    assert_eq!(pool.get("spam", "a").unwrap(), number(11.));
+    assert_eq!(pool.get("spam", "b").unwrap(), number(2.));
+    assert!(pool.ttl("spam", "b").unwrap().is_some());
+    assert_eq!(
+        pool.get("spam", "c").unwrap(),
+        Value::Array(vec![number(3.)])
+    );
+    assert!(!pool.exists("spam", "d").unwrap());
+    assert_eq!(pool.files_on_disk().unwrap(), vec!["spam".to_owned()]);
+
+    // writing compacts the journal into the file
+    pool.write("spam").unwrap();
+    assert!(!path.join("spam.journal").exists());
+}
+
+#[test]
+fn names_of_files_next_to_storages_are_refused() {
+    let path = storage_dir("reserved");
+
+    let pool = new_pool().journal().open(&path, "spam");
+    pool.set("spam", "a", &number(1.), None).unwrap();
+
+    for name in &["spam.journal", "spam.lock", "spam.damaged", "spam.tmp"] {
+        let err = pool.open(name).unwrap_err();
+        assert!(
+            matches!(err.downcast_ref(), Some(StorageError::InvalidName(_))),
+            "{}",
+            name
+        );
+    }
+
+    drop(pool);
+
+    let pool = new_pool().journal().open(&path, "spam");
+    pool.read("spam").unwrap();
+    assert_eq!(pool.get("spam", "a").unwrap(), number(1.));
+}
+
+#[test]
+fn closing_discards_journaled_changes() {
+    let path = storage_dir("close");
+
+    let pool = new_pool().journal().open(&path, "spam");
+    pool.set("spam", "k", &number(1.), None).unwrap();
+    pool.write("spam").unwrap();
+    pool.set("spam", "k", &number(999.), None).unwrap();
+    pool.close("spam").unwrap();
+    assert!(!path.join("spam.journal").exists());
+
+    pool.open("spam").unwrap();
+    pool.read("spam").unwrap();
+    assert_eq!(pool.get("spam", "k").unwrap(), number(1.));
+}
+
+#[test]
+fn storage_never_written() {
+    let path = storage_dir("unwritten");
+
+    let pool = new_pool().journal().open(&path, "spam");
+    pool.import_text("spam", r#"[["a", 1], ["b", 2]]"#).unwrap();
+    drop(pool);
+
+    let pool = new_pool().journal().open(&path, "spam");
+    pool.read("spam").unwrap();
+    assert_eq!(pool.get("spam", "b").unwrap(), number(2.));
+}
+
+#[test]
+fn incomplete_record_is_cut_off() {
+    let path = storage_dir("incomplete");
+
+    let pool = new_pool().journal().open(&path, "spam");
+    pool.set("spam", "a", &number(1.), None).unwrap();
+    drop(pool);
+
+    // a record the crash interrupted
+    let journal = path.join("spam.journal");
+    let len = fs::metadata(&journal).unwrap().len();
+    let mut file = OpenOptions::new().append(true).open(&journal).unwrap();
+    file.write_all(&[100, 0, 0, 0, 1, 2]).unwrap();
+    drop(file);
+
+    let pool = new_pool().journal().open(&path, "spam");
+    assert_eq!(fs::metadata(&journal).unwrap().len(), len);
+
+    pool.set("spam", "b", &number(2.), None).unwrap();
+    pool.read("spam").unwrap();
+    assert_eq!(pool.get("spam", "a").unwrap(), number(1.));
+    assert_eq!(pool.get("spam", "b").unwrap(), number(2.));
+}
+
+#[test]
+fn journal_is_encrypted() {
+    let path = storage_dir("encrypted");
+    let key: EncryptionKey = "a".repeat(64).parse().unwrap();
+
+    let pool = new_pool()
+        .journal()
+        .key(Some(key.clone()))
+        .open(&path, "spam");
+    pool.set("spam", "secret", &Value::String("hunter2".to_owned()), None)
+        .unwrap();
+    drop(pool);
+
+    let bytes = fs::read(path.join("spam.journal")).unwrap();
+    assert!(!bytes.windows(7).any(|window| window == b"hunter2"));
+
+    let pool = new_pool().key(Some(key)).open(&path, "spam");
+    pool.read("spam").unwrap();
+    assert_eq!(
+        pool.get("spam", "secret").unwrap(),
+        Value::String("hunter2".to_owned())
+    );
+}
+
+#[test]
+fn damaged_record_in_the_middle_is_an_error() {
+    let path = storage_dir("damaged");
+
+    let pool = new_pool().journal().open(&path, "spam");
+    pool.set("spam", "a", &number(1.), None).unwrap();
+    pool.set("spam", "b", &number(2.), None).unwrap();
+    drop(pool);
+
+    // flip a byte in the payload of the first record
+    let journal = path.join("spam.journal");
+    let mut bytes = fs::read(&journal).unwrap();
+    bytes[10] ^= 0xff;
+    fs::write(&journal, &bytes).unwrap();
+
+    let pool = new_pool().journal().build(&path);
+    assert!(matches!(
+        pool.open("spam").unwrap_err().downcast_ref(),
+        Some(StorageError::Deserialize)
+    ));
+    // the records after it are kept
+    assert_eq!(fs::read(&journal).unwrap(), bytes);
+}
+
+#[test]
+fn encrypted_records_belong_to_their_storage() {
+    let path = storage_dir("moved");
+    let key: EncryptionKey = "a".repeat(64).parse().unwrap();
+
+    let pool = new_pool().journal().key(Some(key)).build(&path);
+    pool.open("spam").unwrap();
+    pool.open("eggs").unwrap();
+    pool.set("spam", "secret", &number(1.), None).unwrap();
+    fs::copy(path.join("spam.journal"), path.join("eggs.journal")).unwrap();
+
+    assert!(matches!(
+        pool.read("eggs").unwrap_err().downcast_ref(),
+        Some(StorageError::WrongKey)
+    ));
+}
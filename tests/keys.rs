@@ -0,0 +1,174 @@
+mod harness;
+
+use arma_storage::{KeyFilter, StoragePool, Value};
+use harness::{call_alt, new_pool, number, ok, ok_with, storage_dir, string};
+use std::{slice, thread, time::Duration};
+
+fn keys(pool: &StoragePool, filter: KeyFilter) -> Vec<String> {
+    pool.keys("spam", &filter, 0, None).unwrap()
+}
+
+fn glob(pattern: &str) -> KeyFilter {
+    KeyFilter::Glob(pattern.to_owned())
+}
+
+fn strings(strings: &[&str]) -> Vec<String> {
+    strings.iter().map(|string| (*string).to_owned()).collect()
+}
+
+fn players(name: &str) -> StoragePool {
+    let pool = new_pool().open(storage_dir(name), "spam");
+
+    for key in &[
+        "player_2_money",
+        "player_1_money",
+        "player_10_money",
+        "player_1_rank",
+        "vehicle_1",
+    ] {
+        pool.set("spam", key, &number(1.), None).unwrap();
+    }
+
+    pool
+}
+
+#[test]
+fn keys_are_sorted() {
+    let pool = players("sorted");
+
+    assert_eq!(
+        keys(&pool, KeyFilter::All),
+        strings(&[
+            "player_10_money",
+            "player_1_money",
+            "player_1_rank",
+            "player_2_money",
+            "vehicle_1",
+        ])
+    );
+}
+
+#[test]
+fn prefix() {
+    let pool = players("prefix");
+
+    assert_eq!(
+        keys(&pool, KeyFilter::Prefix("player_1".to_owned())),
+        strings(&["player_10_money", "player_1_money", "player_1_rank"])
+    );
+    assert_eq!(
+        keys(&pool, KeyFilter::Prefix("player_1_".to_owned())),
+        strings(&["player_1_money", "player_1_rank"])
+    );
+    assert!(keys(&pool, KeyFilter::Prefix("tank".to_owned())).is_empty());
+}
+
+#[test]
+fn glob_patterns() {
+    let pool = players("glob");
+
+    assert_eq!(
+        keys(&pool, glob("player_*_money")),
+        strings(&["player_10_money", "player_1_money", "player_2_money"])
+    );
+    assert_eq!(
+        keys(&pool, glob("player_?_money")),
+        strings(&["player_1_money", "player_2_money"])
+    );
+    assert_eq!(
+        keys(&pool, glob("player_1*")),
+        strings(&["player_10_money", "player_1_money", "player_1_rank"])
+    );
+    assert_eq!(
+        keys(&pool, glob("*_1*")),
+        strings(&[
+            "player_10_money",
+            "player_1_money",
+            "player_1_rank",
+            "vehicle_1",
+        ])
+    );
+    // backtracks past the first `_` the `*` could stop at
+    assert_eq!(keys(&pool, glob("*_rank")), strings(&["player_1_rank"]));
+    assert_eq!(keys(&pool, glob("vehicle_1")), strings(&["vehicle_1"]));
+    assert_eq!(keys(&pool, glob("*")).len(), 5);
+    assert_eq!(keys(&pool, glob("vehicle_?")), strings(&["vehicle_1"]));
+    assert!(keys(&pool, glob("vehicle_??")).is_empty());
+    assert!(keys(&pool, glob("vehicle")).is_empty());
+    assert!(keys(&pool, glob("*_money_*")).is_empty());
+}
+
+#[test]
+fn expired_keys_are_skipped() {
+    let pool = players("expired");
+    pool.set(
+        "spam",
+        "player_3_money",
+        &number(1.),
+        Some(Duration::from_millis(1)),
+    )
+    .unwrap();
+    thread::sleep(Duration::from_millis(10));
+
+    assert_eq!(
+        keys(&pool, KeyFilter::Prefix("player_3".to_owned())),
+        Vec::<String>::new()
+    );
+    assert_eq!(keys(&pool, glob("player_?_money")).len(), 2);
+}
+
+#[test]
+fn offset_and_limit() {
+    let pool = players("offset");
+    let page = |offset, limit| pool.keys("spam", &glob("player_*"), offset, limit).unwrap();
+
+    assert_eq!(
+        page(1, Some(2)),
+        strings(&["player_1_money", "player_1_rank"])
+    );
+    assert_eq!(page(3, None), strings(&["player_2_money"]));
+    assert_eq!(page(3, Some(10)), strings(&["player_2_money"]));
+    assert!(page(4, Some(1)).is_empty());
+    assert!(page(100, None).is_empty());
+    assert!(page(0, Some(0)).is_empty());
+}
+
+#[test]
+fn through_the_extension() {
+    let storage = string("keys");
+    let strings = |keys: &[&str]| Value::Array(keys.iter().map(|key| string(key)).collect());
+
+    assert_eq!(call_alt("open", slice::from_ref(&storage)), ok());
+
+    for key in &["b_1", "a_1", "a_2", "a_10"] {
+        assert_eq!(
+            call_alt("set", &[storage.clone(), string(key), number(1.)]),
+            ok()
+        );
+    }
+
+    assert_eq!(
+        call_alt("keys", slice::from_ref(&storage)),
+        ok_with(strings(&["a_1", "a_10", "a_2", "b_1"]))
+    );
+    assert_eq!(
+        call_alt("keys", &[storage.clone(), number(1.), number(2.)]),
+        ok_with(strings(&["a_10", "a_2"]))
+    );
+    assert_eq!(
+        call_alt("keysWithPrefix", &[storage.clone(), string("a_")]),
+        ok_with(strings(&["a_1", "a_10", "a_2"]))
+    );
+    assert_eq!(
+        call_alt(
+            "keysMatching",
+            &[storage.clone(), string("a_?"), number(1.)]
+        ),
+        ok_with(strings(&["a_2"]))
+    );
+    assert_eq!(
+        call_alt("keysMatching", &[storage.clone(), string("c*"), number(5.)]),
+        ok_with(strings(&[]))
+    );
+    assert_eq!(call_alt("close", &[storage]), ok());
+}
@@ -0,0 +1,172 @@
+mod harness;
+
+use arma_storage::{Limits, StorageError, Value};
+use harness::{call_alt, new_pool, ok, storage_dir, string};
+use std::{env, sync::Barrier, thread};
+
+fn is_exceeded<T: std::fmt::Debug>(result: anyhow::Result<T>) -> bool {
+    matches!(
+        result.unwrap_err().downcast_ref(),
+        Some(StorageError::LimitExceeded { .. })
+    )
+}
+
+fn text(len: usize) -> Value {
+    Value::String("x".repeat(len))
+}
+
+#[test]
+fn max_keys() {
+    let pool = new_pool()
+        .limits(Limits {
+            max_keys: Some(2),
+            ..Limits::default()
+        })
+        .build(storage_dir("keys"));
+    pool.open("spam").unwrap();
+
+    pool.set("spam", "a", &Value::Number(1.), None).unwrap();
+    pool.set("spam", "b", &Value::Number(1.), None).unwrap();
+    assert!(is_exceeded(pool.set("spam", "c", &Value::Number(1.), None)));
+    assert!(is_exceeded(pool.increment("spam", "c", 1.)));
+
+    // existing keys can still be changed
+    pool.set("spam", "a", &Value::Number(2.), None).unwrap();
+    pool.increment("spam", "b", 1.).unwrap();
+
+    let entries = vec![
+        ("a".to_owned(), Value::Number(3.)),
+        ("c".to_owned(), Value::Number(3.)),
+    ];
+    assert!(is_exceeded(pool.set_many("spam", &entries)));
+    assert_eq!(pool.get("spam", "a").unwrap(), Value::Number(2.));
+}
+
+#[test]
+fn max_sizes() {
+    let pool = new_pool()
+        .limits(Limits {
+            max_value_size: Some(100),
+            max_storage_size: Some(250),
+            ..Limits::default()
+        })
+        .build(storage_dir("sizes"));
+    pool.open("spam").unwrap();
+
+    assert!(is_exceeded(pool.set("spam", "a", &text(100), None)));
+    pool.set("spam", "a", &text(80), None).unwrap();
+    pool.set("spam", "b", &text(80), None).unwrap();
+    assert!(is_exceeded(pool.set("spam", "c", &text(80), None)));
+
+    // a failed push leaves the array unchanged
+    pool.set("spam", "c", &Value::Array(vec![]), None).unwrap();
+    assert!(is_exceeded(pool.push("spam", "c", &text(60))));
+    assert_eq!(pool.get("spam", "c").unwrap(), Value::Array(vec![]));
+
+    // shrinking is always allowed and frees space
+    pool.erase("spam", "a").unwrap();
+    pool.push("spam", "c", &text(60)).unwrap();
+
+    let stats = pool.stats("spam").unwrap();
+    assert_eq!(stats.keys, 2);
+    assert_eq!(stats.size, pool.stats("spam").unwrap().total_size);
+}
+
+#[test]
+fn max_total_size_and_open_storages() {
+    let pool = new_pool()
+        .limits(Limits {
+            max_total_size: Some(150),
+            max_open_storages: Some(2),
+            ..Limits::default()
+        })
+        .build(storage_dir("total"));
+    pool.open("spam").unwrap();
+    pool.open("eggs").unwrap();
+    assert!(is_exceeded(pool.open("ham")));
+
+    pool.set("spam", "a", &text(80), None).unwrap();
+    assert!(is_exceeded(pool.set("eggs", "a", &text(80), None)));
+
+    // closing a storage frees its size and slot
+    pool.close("spam").unwrap();
+    pool.set("eggs", "a", &text(80), None).unwrap();
+    pool.open("ham").unwrap();
+}
+
+#[test]
+fn total_size_survives_closing_while_in_use() {
+    let pool = new_pool()
+        .limits(Limits::default())
+        .build(storage_dir("closing"));
+    pool.open("empty").unwrap();
+
+    for _ in 0..50 {
+        pool.open("spam").unwrap();
+        let started = Barrier::new(5);
+
+        thread::scope(|scope| {
+            for worker in 0..4 {
+                let (pool, started) = (&pool, &started);
+                scope.spawn(move || {
+                    started.wait();
+                    for i in 0..200 {
+                        let _ = pool.set("spam", &format!("{}_{}", worker, i), &text(10), None);
+                    }
+                });
+            }
+
+            started.wait();
+            pool.close("spam").unwrap();
+        });
+    }
+
+    assert_eq!(pool.stats("empty").unwrap().total_size, 0);
+}
+
+#[test]
+fn import_is_checked() {
+    let pool = new_pool()
+        .limits(Limits {
+            max_keys: Some(1),
+            ..Limits::default()
+        })
+        .build(storage_dir("import"));
+    pool.open("spam").unwrap();
+
+    assert!(is_exceeded(
+        pool.import_text("spam", r#"[["a", 1], ["b", 2]]"#)
+    ));
+    pool.import_text("spam", r#"[["a", 1]]"#).unwrap();
+}
+
+#[test]
+fn stats_and_error_code() {
+    env::set_var("ARMA_STORAGE_MAX_KEYS", "1");
+
+    let spam = [string("spam")];
+    assert_eq!(call_alt("open", &spam), ok());
+    assert_eq!(
+        call_alt("set", &[string("spam"), string("a"), Value::Number(1.)]),
+        ok()
+    );
+
+    let (_, code) = call_alt("set", &[string("spam"), string("b"), Value::Number(1.)]);
+    assert_eq!(code, 21);
+
+    let (stats, code) = call_alt("stats", &spam);
+    assert_eq!(code, 0);
+    match stats {
+        Value::Array(stats) => {
+            assert_eq!(
+                stats[0],
+                Value::Array(vec![string("keys"), Value::Number(1.)])
+            );
+            assert_eq!(
+                stats[1],
+                Value::Array(vec![string("maxKeys"), Value::Number(1.)])
+            );
+        }
+        _ => panic!("stats are not an array"),
+    }
+}
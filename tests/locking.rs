@@ -0,0 +1,85 @@
+mod harness;
+
+use arma_storage::{LockMode, StorageError, StoragePool};
+use harness::storage_dir;
+
+fn is_locked(result: anyhow::Result<()>) -> bool {
+    matches!(
+        result.unwrap_err().downcast_ref(),
+        Some(StorageError::LockedByOtherProcess(_))
+    )
+}
+
+#[test]
+fn exclusive_lock_is_released_on_close() {
+    let path = storage_dir("exclusive");
+    let server = StoragePool::new(&path);
+    let other = StoragePool::new(&path);
+
+    server.open("spam").unwrap();
+    assert!(is_locked(other.open("spam")));
+    assert!(is_locked(other.open_with("spam", LockMode::Shared)));
+
+    server.close("spam").unwrap();
+    other.open("spam").unwrap();
+}
+
+#[test]
+fn shared_locks_are_read_only() {
+    let path = storage_dir("shared");
+    let first = StoragePool::new(&path);
+    let second = StoragePool::new(&path);
+
+    first.open_with("spam", LockMode::Shared).unwrap();
+    second.open_with("spam", LockMode::Shared).unwrap();
+
+    assert!(matches!(
+        first.write("spam").unwrap_err().downcast_ref(),
+        Some(StorageError::ReadOnly(_))
+    ));
+
+    let writer = StoragePool::new(&path);
+    assert!(is_locked(writer.open("spam")));
+}
+
+#[test]
+fn locked_storages_are_not_deleted() {
+    let path = storage_dir("delete");
+    let server = StoragePool::new(&path);
+    let other = StoragePool::new(&path);
+
+    server.open("spam").unwrap();
+    server.write("spam").unwrap();
+    assert!(is_locked(other.delete("spam")));
+
+    assert!(path.join("spam.lock").exists());
+
+    server.close("spam").unwrap();
+    other.delete("spam").unwrap();
+    assert!(other.files_on_disk().unwrap().is_empty());
+    assert!(!path.join("spam.lock").exists());
+}
+
+#[test]
+fn open_storages_keep_their_lock_file() {
+    let path = storage_dir("delete_open");
+    let server = StoragePool::new(&path);
+    let other = StoragePool::new(&path);
+
+    server.open("spam").unwrap();
+    server.write("spam").unwrap();
+    server.delete("spam").unwrap();
+    assert!(path.join("spam.lock").exists());
+    assert!(is_locked(other.open("spam")));
+}
+
+#[test]
+fn lock_files_are_not_storages() {
+    let path = storage_dir("files");
+    let pool = StoragePool::new(&path);
+
+    pool.open("spam").unwrap();
+    pool.write("spam").unwrap();
+
+    assert_eq!(pool.files_on_disk().unwrap(), vec!["spam".to_owned()]);
+}
@@ -0,0 +1,41 @@
+use arma_storage::sqf_library;
+use std::{fs, path::Path};
+
+/// The library shipped in `sqf/arma_storage` has to be regenerated with
+/// `arma-storage-cli sqf sqf/arma_storage` whenever the functions change
+#[test]
+fn shipped_library_is_up_to_date() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("sqf/arma_storage");
+    let library = sqf_library();
+
+    for (file, contents) in &library {
+        let shipped = fs::read_to_string(directory.join(file))
+            .unwrap_or_else(|err| panic!("{} is missing: {}", file, err));
+
+        assert_eq!(&shipped, contents, "{} is outdated", file);
+    }
+
+    let shipped = fs::read_dir(directory.join("functions")).unwrap().count();
+    assert_eq!(
+        shipped + 1,
+        library.len(),
+        "sqf/arma_storage has extra files"
+    );
+}
+
+#[test]
+fn every_function_has_a_wrapper() {
+    let library = sqf_library();
+    let config = &library
+        .iter()
+        .find(|(file, _)| file == "CfgFunctions.hpp")
+        .unwrap()
+        .1;
+
+    for name in ["call", "get", "set", "addCallback", "erase"] {
+        let file = format!("functions/fn_{}.sqf", name);
+
+        assert!(library.iter().any(|(path, _)| *path == file), "{}", file);
+        assert!(config.contains(&format!("class {} ", name)), "{}", name);
+    }
+}
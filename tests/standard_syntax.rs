@@ -0,0 +1,217 @@
+mod harness;
+
+use arma_storage::{ErrorCodes, Value};
+use harness::{call, call_args, error, ok, ok_with, string};
+use std::slice;
+
+#[test]
+fn string_syntax() {
+    assert_eq!(call("open|plain"), ok());
+    assert_eq!(call("set|plain|rank|\"Major\""), ok());
+    assert_eq!(call("get|plain|rank"), ok_with(string("Major")));
+    assert_eq!(call("exists|plain|rank"), ok_with(Value::Boolean(true)));
+    assert_eq!(call("erase|plain|rank"), ok());
+    assert_eq!(call("exists|plain|rank"), ok_with(Value::Boolean(false)));
+    assert_eq!(call("set|plain|motto|\"Fight||Flight\""), ok());
+    assert_eq!(call("get|plain|motto"), ok_with(string("Fight|Flight")));
+    assert_eq!(call("close|plain"), ok());
+}
+
+#[test]
+fn args_syntax() {
+    let storage = string("args");
+
+    assert_eq!(call_args("open", slice::from_ref(&storage)), ok());
+    assert_eq!(
+        call_args(
+            "increment",
+            &[storage.clone(), string("kills"), Value::Number(2.)]
+        ),
+        ok_with(Value::Number(2.))
+    );
+    assert_eq!(
+        call_args("storages", &[]),
+        ok_with(Value::Array(vec![storage.clone()]))
+    );
+    assert_eq!(call_args("close", &[storage]), ok());
+}
+
+#[test]
+fn quoted_keys() {
+    let storage = string("quoted");
+    let keys = [string("say \"hi\""), string("\"quoted\"")];
+
+    assert_eq!(call_args("open", slice::from_ref(&storage)), ok());
+
+    for (value, key) in keys.iter().enumerate() {
+        assert_eq!(
+            call_args(
+                "set",
+                &[storage.clone(), key.clone(), Value::Number(value as f32)]
+            ),
+            ok()
+        );
+    }
+
+    assert_eq!(
+        call_args("keys", slice::from_ref(&storage)),
+        ok_with(Value::Array(vec![keys[1].clone(), keys[0].clone()]))
+    );
+    assert_eq!(
+        call_args("get", &[storage.clone(), keys[1].clone()]),
+        ok_with(Value::Number(1.))
+    );
+    assert_eq!(
+        call_args("getMany", &[storage.clone(), Value::Array(keys.to_vec())]),
+        ok_with(Value::Array(vec![
+            Value::Array(vec![Value::Number(0.), Value::Number(0.)]),
+            Value::Array(vec![Value::Number(0.), Value::Number(1.)]),
+        ]))
+    );
+    assert_eq!(call_args("close", &[storage]), ok());
+}
+
+#[test]
+fn unknown_function() {
+    let unknown = error(
+        2,
+        "unknownFunction",
+        "Unknown function spam",
+        string("spam"),
+    );
+
+    assert_eq!(call("spam"), unknown);
+    assert_eq!(call_args("spam", &[]), unknown);
+}
+
+#[test]
+fn error_codes_and_names() {
+    let (codes, code) = call("errorCodes");
+    assert_eq!(code, 0);
+
+    let codes = match codes {
+        Value::Array(codes) => codes,
+        codes => panic!("not an array: {:?}", codes),
+    };
+    assert_eq!(codes.len(), ErrorCodes::ALL.len());
+    assert_eq!(
+        codes[1],
+        Value::Array(vec![
+            Value::Number(1.),
+            string("A parameter is not valid UTF-8"),
+            string("invalidUtf8"),
+        ])
+    );
+
+    for error_code in ErrorCodes::ALL {
+        let number = Value::Number(*error_code as i32 as f32);
+
+        assert_eq!(
+            call_args("errorName", slice::from_ref(&number)),
+            ok_with(string(error_code.name()))
+        );
+    }
+
+    let (_, code) = call_args("errorName", &[Value::Number(4.)]);
+    assert_eq!(code, 12);
+}
+
+#[test]
+fn large_results_are_chunked() {
+    let storage = string("large");
+    let value = string(&"\"quoted\" ".repeat(5000));
+
+    assert_eq!(call_args("open", slice::from_ref(&storage)), ok());
+    assert_eq!(
+        call_args("set", &[storage.clone(), string("text"), value.clone()]),
+        ok()
+    );
+
+    let (header, code) = call_args("get", &[storage, string("text")]);
+    assert_eq!(code, 3);
+    let (id, count) = match header {
+        Value::Array(header) => {
+            assert_eq!(header[2], Value::Number(0.));
+            (header[0].clone(), header[1].clone())
+        }
+        header => panic!("not a chunk header: {:?}", header),
+    };
+    let count = match count {
+        Value::Number(count) => count as usize,
+        count => panic!("not a number: {:?}", count),
+    };
+    assert!(count > 1);
+
+    let mut result = String::new();
+    for index in 0..count {
+        match call_args("chunk", &[id.clone(), Value::Number(index as f32)]) {
+            (Value::String(chunk), 0) => result.push_str(&chunk),
+            response => panic!("not a chunk: {:?}", response),
+        }
+    }
+    assert_eq!(result.parse::<Value>().unwrap(), value);
+
+    // the result is gone once its last chunk was fetched
+    let (_, code) = call_args("chunk", &[id, Value::Number(0.)]);
+    assert_eq!(code, 12);
+}
+
+#[test]
+fn many_large_results_wait_at_once() {
+    let storage = string("many_large");
+    let value = string(&"x".repeat(30000));
+
+    assert_eq!(call_args("open", slice::from_ref(&storage)), ok());
+    assert_eq!(
+        call_args("set", &[storage.clone(), string("text"), value.clone()]),
+        ok()
+    );
+
+    let ids: Vec<Value> = (0..20)
+        .map(
+            |_| match call_args("get", &[storage.clone(), string("text")]) {
+                (Value::Array(header), 3) => header[0].clone(),
+                response => panic!("not chunked: {:?}", response),
+            },
+        )
+        .collect();
+
+    // the oldest result is still there
+    let (chunk, code) = call_args("chunk", &[ids[0].clone(), Value::Number(0.)]);
+    assert_eq!(code, 0);
+    assert!(matches!(chunk, Value::String(_)));
+}
+
+#[test]
+fn help_describes_functions() {
+    let get = Value::Array(vec![
+        string("get"),
+        Value::Array(vec![]),
+        Value::Array(vec![
+            Value::Array(vec![
+                string("name"),
+                string("STRING"),
+                Value::Boolean(false),
+            ]),
+            Value::Array(vec![string("key"), string("STRING"), Value::Boolean(false)]),
+        ]),
+        string("Value of a key"),
+    ]);
+    assert_eq!(call_args("help", &[string("get")]), ok_with(get.clone()));
+
+    let (functions, code) = call("help");
+    assert_eq!(code, 0);
+    match functions {
+        Value::Array(functions) => assert!(functions.contains(&get)),
+        functions => panic!("not an array: {:?}", functions),
+    }
+
+    let (erase, _) = call_args("help", &[string("eraseKey")]);
+    assert_eq!(
+        erase.as_sqf(),
+        r#"["erase", ["eraseKey"], [["name", "STRING", false], ["key", "STRING", false]], "Remove a key"]"#
+    );
+
+    let (_, code) = call_args("help", &[string("spam")]);
+    assert_eq!(code, 2);
+}
@@ -0,0 +1,102 @@
+mod harness;
+
+use arma_storage::{KeyFilter, LockMode, Operation, StorageError, StoragePool, Value};
+use harness::{new_pool, number, storage_dir};
+use std::path::PathBuf;
+
+fn set(key: &str, value: f32) -> Operation {
+    Operation::Set(key.to_owned(), number(value))
+}
+
+/// Keys of the storage file as a new pool reads it
+fn on_disk(path: &PathBuf) -> Vec<(String, Value)> {
+    let pool = StoragePool::new(path);
+    pool.open_with("bank", LockMode::Unlocked).unwrap();
+    pool.read("bank").unwrap();
+
+    let mut keys: Vec<_> = pool
+        .keys("bank", &KeyFilter::All, 0, None)
+        .unwrap()
+        .into_iter()
+        .map(|key| {
+            let value = pool.get("bank", &key).unwrap();
+            (key, value)
+        })
+        .collect();
+    keys.sort_by(|a, b| a.0.cmp(&b.0));
+    keys
+}
+
+#[test]
+fn commit_writes_only_the_transaction() {
+    let path = storage_dir("only");
+    let pool = StoragePool::new(&path);
+    pool.open("bank").unwrap();
+    pool.set("bank", "alice", &number(100.), None).unwrap();
+    pool.write("bank").unwrap();
+
+    // not part of the transaction and never written
+    pool.set("bank", "carol", &number(5.), None).unwrap();
+
+    let id = pool.begin("bank").unwrap();
+    pool.stage(id, set("alice", 50.)).unwrap();
+    pool.stage(id, set("bob", 50.)).unwrap();
+    pool.commit(id).unwrap();
+
+    assert_eq!(pool.get("bank", "carol").unwrap(), number(5.));
+    assert_eq!(pool.get("bank", "bob").unwrap(), number(50.));
+    // carol is still not written
+    assert!(pool.storage_info()[0].dirty);
+    assert_eq!(
+        on_disk(&path),
+        vec![
+            ("alice".to_owned(), number(50.)),
+            ("bob".to_owned(), number(50.))
+        ]
+    );
+}
+
+#[test]
+fn failed_commit_changes_nothing() {
+    let path = storage_dir("failed");
+    let pool = StoragePool::new(&path);
+    pool.open("bank").unwrap();
+    pool.set("bank", "alice", &number(100.), None).unwrap();
+    pool.write("bank").unwrap();
+
+    let id = pool.begin("bank").unwrap();
+    pool.stage(id, set("alice", 0.)).unwrap();
+    pool.stage(id, Operation::Erase("bob".to_owned())).unwrap();
+    let err = pool.commit(id).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref(),
+        Some(StorageError::StorageMissingKey(_))
+    ));
+
+    assert_eq!(pool.get("bank", "alice").unwrap(), number(100.));
+    assert_eq!(on_disk(&path), vec![("alice".to_owned(), number(100.))]);
+    assert!(matches!(
+        pool.commit(id).unwrap_err().downcast_ref(),
+        Some(StorageError::UnknownTransaction(_))
+    ));
+}
+
+#[test]
+fn commit_keeps_journaled_changes() {
+    let path = storage_dir("journaled");
+    let pool = new_pool().journal().open(&path, "bank");
+    pool.set("bank", "alice", &number(100.), None).unwrap();
+
+    let id = pool.begin("bank").unwrap();
+    pool.stage(id, set("bob", 50.)).unwrap();
+    pool.commit(id).unwrap();
+    pool.close("bank").unwrap();
+
+    assert_eq!(
+        on_disk(&path),
+        vec![
+            ("alice".to_owned(), number(100.)),
+            ("bob".to_owned(), number(50.))
+        ]
+    );
+}